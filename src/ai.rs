@@ -0,0 +1,303 @@
+use crate::{Enemy, PlayerHead, EnemyHead};
+use crate::enemy::Health;
+use crate::archetype::Archetype;
+use crate::map::Collision;
+use crate::physics::{Physics, Colliders, ground_below};
+use crate::nav::{NavGraph, EdgeKind, jump_reach};
+use crate::rng::GameRng;
+use crate::tick::{TickRate, TickLabel, FixedUpdateStage, playing_tick_set};
+use std::f32::consts::TAU;
+use bevy::prelude::*;
+
+//slower than the player so they can be outrun
+//push a second, the same as the player's it's scaled by the tick length
+pub const ENEMY_VELOCITY: f32 = 210.0;
+pub const ENEMY_JUMP: f32 = 8.0;
+const AIR_CONTROL: f32 = 0.03;
+
+//past this the player can't be seen at all, closer ranges come from the archetype
+const SIGHT_RANGE: f32 = 60.0;
+
+//in seconds
+const LOSE_SIGHT_TIME: f32 = 3.0;
+const STRAFE_TIME: f32 = 4.0;
+const REPOSITION_TIME: f32 = 3.0;
+const WANDER_TIME: f32 = 3.0;
+
+const REPOSITION_DISTANCE: f32 = 12.0;
+//close enough to count as having got somewhere
+const ARRIVE_DISTANCE: f32 = 1.0;
+
+//bottom of the hitbox relative to the head
+pub const FEET: f32 = -6.5;
+//how far ahead to check there's still floor
+const EDGE_PROBE: f32 = 1.5;
+//anything deeper than this is a fall rather than a step down
+const MAX_DROP: f32 = 3.0;
+
+pub struct AiPlugin;
+
+//everything in the ai step is chained, they share the rng and each other's transforms
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum AiLabel {
+	Think,
+	Move,
+	Ability,
+}
+
+impl Plugin for AiPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_system_set_to_stage(
+			FixedUpdateStage,
+			playing_tick_set()
+				.with_system(enemy_think_system.label(AiLabel::Think).label(TickLabel::Ai).after(TickLabel::Input))
+				.with_system(enemy_move_system.label(AiLabel::Move).label(TickLabel::Ai).after(AiLabel::Think))
+		);
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BrainState {
+	//wander about until the player shows up
+	Patrol,
+	//head for the player, or where they were last seen
+	Chase,
+	//circle the player at a distance
+	Strafe,
+	//back off when too close or nearly dead
+	Flee,
+	//walk somewhere else after strafing for a while
+	Reposition,
+}
+
+#[derive(Component)]
+pub struct EnemyBrain {
+	pub state: BrainState,
+	pub state_time: f32,
+	pub last_seen: Option<Vec3>,
+	pub unseen_time: f32,
+	//where it's going or what it's avoiding, depending on the state
+	pub target: Vec3,
+	//horizontal direction it wants to move this tick, zero to stand still
+	pub heading: Vec3,
+	//heading across a gap the nav graph says can be jumped
+	pub leap: bool,
+	wander: Vec3,
+	wander_time: f32,
+	strafe_sign: f32,
+}
+
+impl Default for EnemyBrain {
+	fn default() -> Self {
+		EnemyBrain {
+			state: BrainState::Patrol,
+			state_time: 0.0,
+			last_seen: None,
+			unseen_time: 0.0,
+			target: Vec3::ZERO,
+			heading: Vec3::ZERO,
+			leap: false,
+			wander: Vec3::Z,
+			wander_time: 0.0,
+			strafe_sign: 1.0,
+		}
+	}
+}
+
+impl EnemyBrain {
+	fn change_state(&mut self, state: BrainState, position: Vec3, rng: &mut GameRng) {
+		if self.state == state {
+			return;
+		}
+		self.state = state;
+		self.state_time = 0.0;
+
+		match state {
+			BrainState::Strafe => {
+				self.strafe_sign = if rng.f32() < 0.5 { -1.0 } else { 1.0 };
+			},
+			BrainState::Reposition => {
+				self.target = position + random_direction(rng) * REPOSITION_DISTANCE;
+			},
+			BrainState::Patrol => {
+				self.wander_time = 0.0;
+			},
+			BrainState::Chase | BrainState::Flee => {},
+		}
+	}
+}
+
+pub fn enemy_think_system(
+	mut query: Query<(&Transform, &Health, &Archetype, &mut EnemyBrain), EnemyHead>,
+	player_query: Query<&Transform, PlayerHead>,
+	colliders: Res<Colliders>,
+	nav_graph: Res<NavGraph>,
+	tick_rate: Res<TickRate>,
+	mut rng: ResMut<GameRng>,
+) {
+	let delta = tick_rate.step();
+	let player_pos = player_query.get_single().unwrap().translation;
+
+	for (trans, health, archetype, mut brain) in query.iter_mut() {
+		let movement = &archetype.movement;
+		//one tick's push at its own speed decides which jumps are worth planning
+		let reach = jump_reach(ENEMY_VELOCITY * movement.speed, delta);
+		let position = trans.translation;
+		brain.state_time += delta;
+
+		let distance = position.distance(player_pos);
+		let sees_player = distance < SIGHT_RANGE && colliders.line_of_sight(position, player_pos);
+		if sees_player {
+			brain.last_seen = Some(player_pos);
+			brain.unseen_time = 0.0;
+		} else {
+			brain.unseen_time += delta;
+		}
+
+		let state = if sees_player {
+			if distance < movement.flee_range || (health.amount == 1 && distance < movement.strafe_range) {
+				BrainState::Flee
+			} else if distance > movement.strafe_range {
+				BrainState::Chase
+			} else if brain.state == BrainState::Reposition {
+				let arrived = horizontal(brain.target - position).length() < ARRIVE_DISTANCE;
+				if arrived || brain.state_time > REPOSITION_TIME {
+					BrainState::Strafe
+				} else {
+					BrainState::Reposition
+				}
+			} else if brain.state == BrainState::Strafe && brain.state_time > STRAFE_TIME {
+				BrainState::Reposition
+			} else {
+				BrainState::Strafe
+			}
+		} else if brain.last_seen.is_some() && brain.unseen_time < LOSE_SIGHT_TIME {
+			BrainState::Chase
+		} else {
+			brain.last_seen = None;
+			BrainState::Patrol
+		};
+		brain.change_state(state, position, &mut rng);
+
+		let to_player = horizontal(player_pos - position).normalize_or_zero();
+		brain.leap = false;
+		brain.heading = match brain.state {
+			BrainState::Patrol => {
+				brain.wander_time -= delta;
+				if brain.wander_time <= 0.0 {
+					brain.wander = random_direction(&mut rng);
+					brain.wander_time = WANDER_TIME;
+				}
+				brain.target = position + brain.wander;
+				brain.wander
+			},
+			BrainState::Chase => {
+				brain.target = brain.last_seen.unwrap_or(player_pos);
+				route_heading(&mut brain, position, &nav_graph, reach)
+			},
+			BrainState::Strafe => {
+				brain.target = player_pos;
+				Vec3::Y.cross(to_player) * brain.strafe_sign
+			},
+			BrainState::Flee => {
+				brain.target = player_pos;
+				-to_player
+			},
+			BrainState::Reposition => {
+				route_heading(&mut brain, position, &nav_graph, reach)
+			},
+		};
+	}
+}
+
+pub fn enemy_move_system(
+	mut query: Query<(&mut Transform, &mut Physics, &Archetype, &mut EnemyBrain), EnemyHead>,
+	collision_query: Query<&Transform, (With<Collision>, Without<Enemy>)>,
+	tick_rate: Res<TickRate>,
+) {
+	let delta = tick_rate.step();
+	let walls: Vec<(Vec3, Vec3)> = collision_query.iter()
+		.map(|trans| (trans.translation, trans.scale))
+		.collect();
+
+	for (mut trans, mut physics, archetype, mut brain) in query.iter_mut() {
+		let mut heading = brain.heading;
+
+		if physics.grounded && heading != Vec3::ZERO {
+			let feet = trans.translation + Vec3::new(0.0, FEET, 0.0);
+			if !has_floor(feet + heading * EDGE_PROBE, feet.y, &walls) {
+				//only jump when the route says there's something to land on
+				if brain.leap {
+					physics.velocity.y += ENEMY_JUMP;
+				} else {
+					if brain.state == BrainState::Patrol {
+						brain.wander = -brain.wander;
+					}
+					heading = Vec3::ZERO;
+				}
+			}
+		}
+
+		let velocity = if physics.grounded {
+			ENEMY_VELOCITY * archetype.movement.speed * delta
+		} else {
+			ENEMY_VELOCITY * archetype.movement.speed * AIR_CONTROL * delta
+		};
+		physics.velocity += heading * velocity;
+
+		//face whatever it's paying attention to, the player when strafing or fleeing
+		let facing = match brain.state {
+			BrainState::Strafe | BrainState::Flee => horizontal(brain.target - trans.translation),
+			_ => heading,
+		};
+		if facing.length_squared() > f32::EPSILON {
+			trans.rotation = Quat::from_rotation_y(f32::atan2(facing.x, facing.z));
+		}
+	}
+}
+
+//follow the nav graph towards the target, straight at it when there's no route
+fn route_heading(brain: &mut EnemyBrain, position: Vec3, nav_graph: &NavGraph, reach: f32) -> Vec3 {
+	let feet = position + Vec3::new(0.0, FEET, 0.0);
+	let target_feet = brain.target + Vec3::new(0.0, FEET, 0.0);
+
+	let next_edge = nav_graph.route(feet, target_feet, reach)
+		.and_then(|path| path.into_iter().next());
+	let aim = match next_edge {
+		Some(edge) => {
+			//walk to where the edge leaves from, then head across
+			let to_start = horizontal(edge.from_point - feet);
+			if to_start.length() > ARRIVE_DISTANCE {
+				edge.from_point
+			} else {
+				brain.leap = edge.kind == EdgeKind::Jump;
+				edge.to_point
+			}
+		},
+		None => target_feet,
+	};
+
+	let to_aim = horizontal(aim - feet);
+	if to_aim.length() < ARRIVE_DISTANCE {
+		Vec3::ZERO
+	} else {
+		to_aim.normalize()
+	}
+}
+
+fn has_floor(point: Vec3, feet_height: f32, walls: &[(Vec3, Vec3)]) -> bool {
+	//a little above so a floor at exactly the same height counts
+	match ground_below(Vec3::new(point.x, feet_height + 0.5, point.z), walls) {
+		Some(top) => feet_height - top < MAX_DROP,
+		None => false,
+	}
+}
+
+fn horizontal(vec: Vec3) -> Vec3 {
+	Vec3::new(vec.x, 0.0, vec.z)
+}
+
+fn random_direction(rng: &mut GameRng) -> Vec3 {
+	let angle = rng.f32() * TAU;
+	Vec3::new(angle.sin(), 0.0, angle.cos())
+}
@@ -0,0 +1,150 @@
+use crate::rng::GameRng;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+//each guess at the flight time gets closer, a few is plenty at these speeds
+const LEAD_ITERATIONS: usize = 6;
+
+//how well an archetype shoots
+#[derive(Clone, Debug, Deserialize)]
+pub struct Accuracy {
+	//how much of the target's movement it allows for, 0 aims where they are, 1 where they'll be
+	pub lead: f32,
+	//most it can be off by either way, in radians
+	pub spread: f32,
+	//throw it up high and drop it on the target instead of the flat shot
+	#[serde(default)]
+	pub lob: bool,
+}
+
+impl Default for Accuracy {
+	fn default() -> Self {
+		Accuracy {
+			lead: 1.0,
+			spread: 0.0,
+			lob: false,
+		}
+	}
+}
+
+//launch velocity of the given speed that lands on something moving at target_velocity
+//none when it's out of range at that speed
+pub fn ballistic_velocity(
+	origin: Vec3,
+	target: Vec3,
+	target_velocity: Vec3,
+	speed: f32,
+	gravity: f32,
+	lob: bool,
+) -> Option<Vec3> {
+	let mut flight_time = origin.distance(target) / speed;
+	let mut velocity = None;
+
+	for _ in 0..LEAD_ITERATIONS {
+		let aim_point = target + target_velocity * flight_time;
+		let (launch, time) = launch_velocity(aim_point - origin, speed, gravity, lob)?;
+		velocity = Some(launch);
+		flight_time = time;
+	}
+	velocity
+}
+
+//velocity to cover an offset under gravity at a fixed speed, with how long it takes
+fn launch_velocity(offset: Vec3, speed: f32, gravity: f32, lob: bool) -> Option<(Vec3, f32)> {
+	let flat = Vec3::new(offset.x, 0.0, offset.z);
+	let distance = flat.length();
+	let height = offset.y;
+
+	if gravity <= f32::EPSILON || distance <= f32::EPSILON {
+		//straight line, or straight up and down where the angle doesn't matter
+		let time = offset.length() / speed;
+		return Some((offset.normalize_or_zero() * speed, time));
+	}
+
+	//the usual angle of reach, two answers when it's in range and none when it isn't
+	let speed_squared = speed * speed;
+	let root = speed_squared * speed_squared - gravity * (gravity * distance * distance + 2.0 * height * speed_squared);
+	if root < 0.0 {
+		return None;
+	}
+	let root = root.sqrt();
+	let tan = if lob {
+		(speed_squared + root) / (gravity * distance)
+	} else {
+		(speed_squared - root) / (gravity * distance)
+	};
+
+	let angle = tan.atan();
+	let horizontal_speed = speed * angle.cos();
+	let velocity = flat / distance * horizontal_speed + Vec3::new(0.0, speed * angle.sin(), 0.0);
+	Some((velocity, distance / horizontal_speed))
+}
+
+//knocks a velocity off course by up to spread each way, keeping its speed
+pub fn apply_spread(velocity: Vec3, spread: f32, rng: &mut GameRng) -> Vec3 {
+	if spread <= 0.0 {
+		return velocity;
+	}
+	let yaw = Quat::from_rotation_y(rng.centred() * 2.0 * spread);
+	let pitch_angle = rng.centred() * 2.0 * spread;
+	//straight up or down has no side to pitch around, the yaw will do
+	let side = velocity.cross(Vec3::Y);
+	if side.length_squared() <= f32::EPSILON {
+		return yaw * velocity;
+	}
+	let pitch = Quat::from_axis_angle(side.normalize(), pitch_angle);
+	yaw * pitch * velocity
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bevy::math::const_vec3;
+
+	const ORIGIN: Vec3 = const_vec3!([0.0, 6.0, 0.0]);
+	const SPEED: f32 = 25.0;
+	const GRAVITY: f32 = 6.0;
+
+	//closest a shot gets to a target moving in a straight line, stepping through its flight
+	fn miss_distance(velocity: Vec3, target: Vec3, target_velocity: Vec3) -> f32 {
+		(0..10_000)
+			.map(|step| step as f32 * 0.001)
+			.map(|time| {
+				let shot = ORIGIN + velocity * time + Vec3::new(0.0, -GRAVITY * time * time / 2.0, 0.0);
+				shot.distance(target + target_velocity * time)
+			})
+			.fold(f32::MAX, f32::min)
+	}
+
+	#[test]
+	fn flat_and_lob_both_hit_a_still_target() {
+		let target = Vec3::new(30.0, 3.0, 10.0);
+		let flat = ballistic_velocity(ORIGIN, target, Vec3::ZERO, SPEED, GRAVITY, false).unwrap();
+		let lob = ballistic_velocity(ORIGIN, target, Vec3::ZERO, SPEED, GRAVITY, true).unwrap();
+
+		for velocity in [flat, lob] {
+			assert!((velocity.length() - SPEED).abs() < 0.01);
+			assert!(miss_distance(velocity, target, Vec3::ZERO) < 0.05);
+		}
+		assert!(lob.y > flat.y, "the lob should go higher, {} vs {}", lob, flat);
+	}
+
+	#[test]
+	fn leads_a_moving_target() {
+		let target = Vec3::new(30.0, 3.0, 10.0);
+		let target_velocity = Vec3::new(0.0, 0.0, 5.0);
+		let led = ballistic_velocity(ORIGIN, target, target_velocity, SPEED, GRAVITY, false).unwrap();
+		let unled = ballistic_velocity(ORIGIN, target, Vec3::ZERO, SPEED, GRAVITY, false).unwrap();
+
+		assert!(miss_distance(led, target, target_velocity) < 0.05);
+		//aiming where it is now misses by however far it's moved
+		assert!(miss_distance(unled, target, target_velocity) > 1.0);
+	}
+
+	#[test]
+	fn nothing_when_out_of_range() {
+		let target = Vec3::new(200.0, 3.0, 10.0);
+		assert!(ballistic_velocity(ORIGIN, target, Vec3::ZERO, 12.0, 12.0, false).is_none());
+		assert!(ballistic_velocity(ORIGIN, target, Vec3::ZERO, 12.0, 12.0, true).is_none());
+	}
+}
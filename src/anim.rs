@@ -1,15 +1,19 @@
-use crate::{rotate_around, Sticky, Physics, Head};
+use crate::{rotate_around, Head};
+use crate::physics::{Physics, Colliders};
+use crate::ai::FEET;
+use crate::state::AppState;
+use crate::anim_graph::{AnimGraphs, AnimGraphLoader, load_anim_graphs, anim_choose_system, raise_anim_flags_system};
+use std::collections::HashSet;
 
 use bevy::prelude::*;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
 
-//animation of player
+//animation of stick figures, the clips themselves are in ANIMS_PATH
 
-const NUM_LIMBS: usize = 4;
-
-const LEFT_ARM_INDEX: usize = 0;
-const RIGHT_ARM_INDEX: usize = 1;
-const LEFT_LEG_INDEX: usize = 2;
-const RIGHT_LEG_INDEX: usize = 3;
+pub const ANIMS_PATH: &str = "stick.anims.ron";
 
 pub const MAJOR_HEIGHT: f32 = 3.0;
 pub const MINOR_HEIGHT: f32 = 2.0;
@@ -21,161 +25,258 @@ pub const RIGHT_ARM: Limb = Limb::Arm(Pos::Right);
 pub const LEFT_LEG: Limb = Limb::Leg(Pos::Left);
 pub const RIGHT_LEG: Limb = Limb::Leg(Pos::Right);
 
-//order is left arm, right arm, left leg, right leg
-const IDLE_ANIM_FRAMES: usize = 1;
-const IDLE_ANIM: [f32; NUM_LIMBS * 3 * IDLE_ANIM_FRAMES] = [
-	-0.9, -3.1, 0.1,
-	0.7, -3.2, -0.4,
-	-0.5, -5.9, -0.1,
-	0.5, -5.9, 0.0,
-];
-
-const WALKING_ANIM_FRAMES: usize = 2;
-const WALKING_ANIM: [f32; NUM_LIMBS * 3 * WALKING_ANIM_FRAMES] = [
-	/*0.7, -3.2, -0.4,
-	-0.9, -3.0, 0.5,
-	0.5, -5.8, 0.4,
-	-0.5, -5.9, -0.5,
-
-	-1.0, -2.9, -0.6,
-	0.9, -3.1, 0.5,
-	0.5, -5.8, -0.4,
-	-0.5, -5.9, 0.3,*/
-	-0.6, -3.1, -0.6,
-	1.2, -2.9, 0.5,
-	-0.7, -5.8, 0.4,
-	0.9, -5.7, -0.4,
-
-	-0.7, -2.9, 0.9,
-	1.1, -2.9, -0.7,
-	-0.7, -5.8, -0.5,
-	0.9, -5.6, 0.6,
-];
-
-const JUMPING_ANIM_FRAMES: usize = 1;
-const JUMPING_ANIM: [f32; NUM_LIMBS * 3 * JUMPING_ANIM_FRAMES] = [
-	-1.8, -2.1, 0.4,
-	1.9, -1.3, 0.4,
-	-0.6, -5.5, -1.1,
-	1.0, -5.4, 1.0,
-];
-
-const RUNNING_ANIM_FRAMES: usize = 4;
-const RUNNING_ANIM: [f32; NUM_LIMBS * 3 * RUNNING_ANIM_FRAMES] = [
-	-1.1, -2.9, -0.6,
-	1.1, -2.9, 0.4,
-	-0.9, -5.7, -0.4,
-	1.0, -5.7, 0.4,
-
-	-0.9, -2.9, 0.9,
-	0.9, -2.9, -0.8,
-	-0.8, -5.7, 0.5,
-	0.6, -5.7, -0.9,
-
-	-1.6, -2.4, -0.7,
-	1.4, -2.3, 0.9,
-	-0.5, -5.5, -1.1,
-	0.6, -5.5, 1.1,
-
-	-1.2, -2.5, 0.9,
-	1.2, -2.8, -0.7,
-	-0.8, -5.4, 1.1,
-	0.7, -5.3, -1.3,
-];
-
-const SPIN_ANIM_FRAMES: usize = 1;
-const SPIN_ANIM: [f32; NUM_LIMBS * 3 * SPIN_ANIM_FRAMES] = [
-	-1.8, -1.6, -0.6,
-	1.9, -1.2, 0.5,
-	0.0, -5.9, 0.3,
-	1.8, -4.5, -0.4,
-];
-
-const IDLE_ANIM_TIME: f32 = 1.0;
-const WALKING_ANIM_TIME: f32 = 0.3;
-const RUNNING_ANIM_TIME: f32 = 1.0;
-const JUMPING_ANIM_TIME: f32 = 0.8;
-const SPIN_ANIM_TIME: f32 = 1.0;
-
-//const CHEERING_ANIM_FRAMES: usize = 2;
-
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum PlayerState {
-	Idle,
-	Walking,
-	Running,
-	Jumping,
-	Spin,
-}
-
-impl PlayerState {
-	pub fn get_anim_slice(&self) -> &[f32] {
+//spins the whole body round while it plays
+pub const SPIN: &str = "spin";
+//radians a second
+const SPIN_SPEED: f32 = 6.0;
+
+//a clip that keeps pace with the body never plays faster than this, however hard it's knocked about
+const MAX_PLAYBACK_RATE: f32 = 3.0;
+//how quickly a foot gets onto something raised under it or back down, higher is quicker
+const FOOT_IK_RATE: f32 = 10.0;
+//how far under the floor a foot over a ledge looks for something lower to stand on
+const FOOT_REACH: f32 = 1.0;
+
+//every clip a stick figure can play, reloaded while the game runs when the file changes
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "5f0e8a43-2c7d-4b19-9e61-d3a8b5c4f217"]
+pub struct AnimClips {
+	pub clips: Vec<AnimClip>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimClip {
+	pub name: String,
+	//starts over after the last frame, otherwise it holds there
+	#[serde(default = "default_looping")]
+	pub looping: bool,
+	//how the limbs get from one frame to the next
+	#[serde(default)]
+	pub easing: Easing,
+	//seconds spent fading in from whatever was playing before
+	#[serde(default = "default_blend_in")]
+	pub blend_in: f32,
+	//ground speed the frames are timed for, moving faster or slower plays it faster or slower
+	#[serde(default)]
+	pub ground_speed: Option<f32>,
+	pub frames: Vec<Keyframe>,
+}
+
+fn default_looping() -> bool {
+	true
+}
+
+fn default_blend_in() -> f32 {
+	0.15
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub enum Easing {
+	#[default]
+	Linear,
+	//slow out of one frame and slow into the next
+	EaseInOut,
+	//the same but sharper, most of the move happens in the middle
+	Cubic,
+	//no in between, jumps to each frame as it's reached
+	Step,
+}
+
+impl Easing {
+	//how far between two frames the limbs are, given how far through the time between them it is
+	pub fn apply(&self, amount_through: f32) -> f32 {
+		let t = amount_through.clamp(0.0, 1.0);
 		match self {
-			PlayerState::Idle => IDLE_ANIM.as_slice(),
-			PlayerState::Walking => WALKING_ANIM.as_slice(),
-			PlayerState::Running => RUNNING_ANIM.as_slice(),
-			PlayerState::Jumping => JUMPING_ANIM.as_slice(),
-			PlayerState::Spin => SPIN_ANIM.as_slice(),
+			Easing::Linear => t,
+			Easing::EaseInOut => (1.0 - (t * std::f32::consts::PI).cos()) / 2.0,
+			Easing::Cubic => if t < 0.5 {
+				4.0 * t * t * t
+			} else {
+				1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+			},
+			Easing::Step => if t < 1.0 { 0.0 } else { 1.0 },
 		}
 	}
+}
 
-	pub fn get_anim_num_frames(&self) -> usize {
-		match self {
-			PlayerState::Idle => IDLE_ANIM_FRAMES,
-			PlayerState::Walking => WALKING_ANIM_FRAMES,
-			PlayerState::Running => RUNNING_ANIM_FRAMES,
-			PlayerState::Jumping => JUMPING_ANIM_FRAMES,
-			PlayerState::Spin => SPIN_ANIM_FRAMES,
+//where the end of each limb is relative to the head
+#[derive(Clone, Debug, Deserialize)]
+pub struct Keyframe {
+	//seconds taken to get here from the frame before
+	pub duration: f32,
+	pub left_arm: Vec3,
+	pub right_arm: Vec3,
+	pub left_leg: Vec3,
+	pub right_leg: Vec3,
+	//sent as AnimEvents when the limbs get here
+	#[serde(default)]
+	pub events: Vec<String>,
+}
+
+impl Keyframe {
+	pub fn limb_pos(&self, limb: Limb) -> Vec3 {
+		match limb {
+			Limb::Arm(Pos::Left) => self.left_arm,
+			Limb::Arm(Pos::Right) => self.right_arm,
+			Limb::Leg(Pos::Left) => self.left_leg,
+			Limb::Leg(Pos::Right) => self.right_leg,
 		}
 	}
+}
 
-	pub fn get_anim_time(&self) -> f32 {
-		match self {
-			PlayerState::Idle => IDLE_ANIM_TIME,
-			PlayerState::Walking => WALKING_ANIM_TIME,
-			PlayerState::Running => RUNNING_ANIM_TIME,
-			PlayerState::Jumping => JUMPING_ANIM_TIME,
-			PlayerState::Spin => SPIN_ANIM_TIME,
+impl AnimClips {
+	fn validate(&self) -> Result<(), String> {
+		for (index, clip) in self.clips.iter().enumerate() {
+			if self.clips[..index].iter().any(|other| other.name == clip.name) {
+				return Err(format!("clip \"{}\" is defined twice", clip.name));
+			}
+			if clip.frames.is_empty() {
+				return Err(format!("clip \"{}\" has no frames", clip.name));
+			}
+			if clip.frames.iter().any(|frame| frame.duration <= 0.0) {
+				return Err(format!("clip \"{}\" needs frames longer than 0", clip.name));
+			}
+			if clip.blend_in < 0.0 {
+				return Err(format!("clip \"{}\" has a negative blend time", clip.name));
+			}
+			if clip.ground_speed.is_some_and(|speed| speed <= 0.0) {
+				return Err(format!("clip \"{}\" needs a ground speed above 0", clip.name));
+			}
 		}
+		Ok(())
 	}
-}
 
-impl Default for PlayerState {
-	fn default() -> Self {
-		Self::Idle
+	pub fn get(&self, name: &str) -> Option<&AnimClip> {
+		self.clips.iter().find(|clip| clip.name == name)
 	}
 }
 
-#[derive(Debug, Default, Component)]
-pub struct AnimPos {
-	start_pos: Vec3,
-	end_pos: Vec3,
+impl AnimClip {
+	//where a limb is on the way to frame index
+	pub fn sample(&self, index: usize, amount_through: f32, limb: Limb) -> Vec3 {
+		//the first frame of a clip that doesn't loop has nothing before it, fading in covers getting there
+		let previous = match index {
+			0 if self.looping => self.frames.len() - 1,
+			0 => 0,
+			_ => index - 1,
+		};
+		Vec3::lerp(
+			self.frames[previous].limb_pos(limb),
+			self.frames[index].limb_pos(limb),
+			self.easing.apply(amount_through),
+		)
+	}
+
+	//times normal speed to play at for a body moving at a horizontal speed
+	pub fn playback_rate(&self, speed: f32) -> f32 {
+		match self.ground_speed {
+			Some(ground_speed) => f32::min(speed / ground_speed, MAX_PLAYBACK_RATE),
+			None => 1.0,
+		}
+	}
+
+	//how much of the pose is this clip rather than the one before, seconds after changing to it
+	pub fn blend_weight(&self, blend: f32) -> f32 {
+		if blend >= self.blend_in {
+			1.0
+		} else {
+			Easing::EaseInOut.apply(blend / self.blend_in)
+		}
+	}
 }
 
-impl AnimPos {
-	pub fn calc_curr_pos(&self, amount_through: f32) -> Vec3 {
-		Vec3::lerp(self.start_pos, self.end_pos, amount_through)
+#[derive(Default)]
+pub struct AnimClipLoader;
+
+impl AssetLoader for AnimClipLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let path = load_context.path().display().to_string();
+			let clips: AnimClips = ron::de::from_bytes(bytes)
+				.map_err(|err| anyhow::anyhow!("{}:{}", path, err))?;
+			clips.validate()
+				.map_err(|err| anyhow::anyhow!("{}: {}", path, err))?;
+			load_context.set_default_asset(LoadedAsset::new(clips));
+			Ok(())
+		})
 	}
 
-	pub fn change_pos(&mut self, anim: PlayerState, limb: Limb, amount_through: f32, index: usize) {
-		self.start_pos = self.calc_curr_pos(amount_through);
-		self.end_pos = get_target_pos(anim, limb, index);
+	fn extensions(&self) -> &[&str] {
+		&["anims.ron"]
 	}
+}
 
-	pub fn default_from_limb(limb: Limb, anim: PlayerState) -> AnimPos {
-		let pos = get_target_pos(anim, limb, 0);
-		AnimPos {
-			start_pos: pos,
-			end_pos: pos,
-		}
+pub struct CurrentAnimClips {
+	pub handle: Handle<AnimClips>,
+}
+
+pub fn load_anim_clips(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+) {
+	commands.insert_resource(CurrentAnimClips {
+		handle: asset_server.load(ANIMS_PATH),
+	});
+}
+
+pub struct AnimPlugin;
+
+//a clip reaching a frame with events on it, footsteps and the like
+//clips play on frame time rather than in the tick, so these are for sounds and effects only,
+//anything the simulation did off them would come out different in a replay
+pub struct AnimEvent {
+	//the head playing the clip
+	pub entity: Entity,
+	pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum AnimLabel {
+	Flags,
+	Choose,
+	Update,
+	FootIk,
+}
+
+impl Plugin for AnimPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_asset::<AnimClips>()
+			.init_asset_loader::<AnimClipLoader>()
+			.add_asset::<AnimGraphs>()
+			.init_asset_loader::<AnimGraphLoader>()
+			.add_startup_system(load_anim_clips)
+			.add_startup_system(load_anim_graphs)
+			.add_event::<AnimEvent>()
+			.add_system_set(anim_set(AppState::Playing))
+			//keeps going on the win screen so there's something to celebrate with
+			.add_system_set(anim_set(AppState::Win));
 	}
 }
 
+fn anim_set(state: AppState) -> SystemSet {
+	SystemSet::on_update(state)
+		.with_system(raise_anim_flags_system.label(AnimLabel::Flags))
+		.with_system(anim_choose_system.label(AnimLabel::Choose).after(AnimLabel::Flags))
+		.with_system(update_anims.label(AnimLabel::Update).after(AnimLabel::Choose))
+		.with_system(foot_ik_system.label(AnimLabel::FootIk).after(AnimLabel::Update))
+		.with_system(spin_sticky_system.after(AnimLabel::Update).before(AnimLabel::FootIk))
+}
+
+//where the end of a limb is, and where it was when the clip last changed
+#[derive(Debug, Default, Component)]
+pub struct AnimPos {
+	pub pos: Vec3,
+	blend_from: Vec3,
+	//how far a foot's been raised to stand on whatever's under it, lowered when it's below zero
+	pub lift: f32,
+}
+
 pub fn get_trans_from_pos(limb: Limb, mut pos: Vec3) -> Transform {
-	let (default_pos, pivot) = match limb {
-		Limb::Arm(_) => (get_default_arm_pos(), get_arm_pivot()),
-		Limb::Leg(_) => (get_default_leg_pos(), get_leg_pivot()),
-	};
+	let (default_pos, pivot) = limb_joints(limb);
 
 	let mut trans = Transform::from_translation(default_pos);
 	pos = pos - default_pos;
@@ -186,25 +287,18 @@ pub fn get_trans_from_pos(limb: Limb, mut pos: Vec3) -> Transform {
 	trans
 }
 
-fn get_target_pos(anim: PlayerState, limb: Limb, index: usize) -> Vec3 {
-	let anim_slice = anim.get_anim_slice();
-
-	let limb_index = match limb {
-		Limb::Arm(arm) => match arm {
-			Pos::Left => LEFT_ARM_INDEX,
-			Pos::Right => RIGHT_ARM_INDEX,
-		},
-		Limb::Leg(leg) => match leg {
-			Pos::Left => LEFT_LEG_INDEX,
-			Pos::Right => RIGHT_LEG_INDEX,
-		},
-	};
-
-	//first split into chunks then skip to correct index then to correct limb index
-	//scuffed mega line
-	let mut anim_iter = anim_slice.chunks(NUM_LIMBS * 3).skip(index).next().unwrap().iter().skip(limb_index * 3);
+//where a limb sits unposed and the point it turns around, in the body
+fn limb_joints(limb: Limb) -> (Vec3, Vec3) {
+	match limb {
+		Limb::Arm(_) => (get_default_arm_pos(), get_arm_pivot()),
+		Limb::Leg(_) => (get_default_leg_pos(), get_leg_pivot()),
+	}
+}
 
-	Vec3::new(*anim_iter.next().unwrap(), *anim_iter.next().unwrap(), *anim_iter.next().unwrap())
+//the far end of a limb posed at pos, in the body
+fn limb_end(limb: Limb, pos: Vec3) -> Vec3 {
+	let (default_pos, pivot) = limb_joints(limb);
+	pivot + (pos - default_pos).normalize() * MINOR_HEIGHT
 }
 
 fn get_default_arm_pos() -> Vec3 {
@@ -235,106 +329,383 @@ pub enum Pos {
 	Right,
 }
 
-pub fn anim_choose_system(
-	mut player_head_query: Query<(&mut AnimInfo, &Physics, &Sticky)>,
-	mut limb_query: Query<(&mut AnimPos, &Limb, &Sticky)>,
+pub fn update_anims(
+    mut player_query: Query<(Entity, &mut AnimInfo, &Physics, &Children)>,
+    children_query: Query<&Children, Without<AnimInfo>>,
+    mut query: Query<(&mut Transform, &mut AnimPos, &Limb)>,
+    clip_assets: Res<Assets<AnimClips>>,
+    current_clips: Res<CurrentAnimClips>,
+    time: Res<Time>,
+    mut anim_events: EventWriter<AnimEvent>,
 ) {
-	for (mut anim_info, physics, sticky) in player_head_query.iter_mut() {
-		let speed = physics.velocity.length_squared();
-
-		let mut changed = false;
-		if !physics.grounded {
-			if anim_info.anim != PlayerState::Jumping {
-				anim_info.change_anim(PlayerState::Jumping);
-				changed = true;
-			}
-		} else {
-			if speed > 3.0 {
-				if anim_info.anim != PlayerState::Running {
-					anim_info.change_anim(PlayerState::Running);
-					changed = true;
-				}
-			} else if speed > 0.5 {
-				if anim_info.anim != PlayerState::Walking {
-					anim_info.change_anim(PlayerState::Walking);
-					changed = true;
+    let delta = time.delta_seconds();
+    let clips = match clip_assets.get(&current_clips.handle) {
+        Some(clips) => clips,
+        None => return,
+    };
+
+    for (entity, mut anim_info, physics, children) in player_query.iter_mut() {
+        //a clip that's gone missing in a reload just leaves the limbs where they are
+        let clip = match clips.get(&anim_info.anim) {
+            Some(clip) => clip,
+            None => continue,
+        };
+        //a reload can take frames away from under us
+        if anim_info.index >= clip.frames.len() {
+            anim_info.index = 0;
+        }
+
+        let speed = Vec3::new(physics.velocity.x, 0.0, physics.velocity.z).length();
+        for reached in anim_info.add_time(delta * clip.playback_rate(speed), clip) {
+            anim_events.send_batch(clip.frames[reached].events.iter().map(|name| AnimEvent {
+                entity,
+                name: name.clone(),
+            }));
+        }
+        let weight = anim_info.blend.map(|blend| clip.blend_weight(blend));
+        for limb_entity in limbs_of(children, &children_query) {
+            if let Ok((mut transform, mut anim_pos, limb)) = query.get_mut(limb_entity) {
+                if anim_info.changed {
+                    //fade out of wherever the last clip got to, even if that was partway through a fade itself
+                    anim_pos.blend_from = anim_pos.pos;
+                }
+                let target = clip.sample(anim_info.index, anim_info.amount_through, *limb);
+                anim_pos.pos = match weight {
+                    Some(weight) => Vec3::lerp(anim_pos.blend_from, target, weight),
+                    None => target,
+                };
+                *transform = get_trans_from_pos(*limb, anim_pos.pos);
+            }
+        }
+        anim_info.changed = false;
+        anim_info.blend = match anim_info.blend {
+            Some(blend) if blend < clip.blend_in => Some(blend + delta),
+            _ => None,
+        };
+    }
+}
+
+//a head with limbs to animate, hanging off it by way of its body
+type AnimatedHead = (With<AnimInfo>, Without<Limb>);
+//the stick hanging off a head that the limbs hang off in turn
+type Body = (With<Children>, Without<AnimInfo>, Without<Limb>);
+
+//raises feet onto anything under them that's higher than the floor the body's standing on,
+//or lowers them onto something a little lower over a ledge, sitting on it the same as they sit on the floor
+//legs are one stick so a foot swings out or down to reach it rather than bending
+pub fn foot_ik_system(
+	head_query: Query<(&Transform, &Physics, &Children), AnimatedHead>,
+	body_query: Query<(&Transform, &Children), Body>,
+	mut limb_query: Query<(&mut Transform, &mut AnimPos, &Limb)>,
+	colliders: Res<Colliders>,
+	time: Res<Time>,
+) {
+	let rate = f32::min(1.0, time.delta_seconds() * FOOT_IK_RATE);
+
+	for (head_trans, physics, head_children) in head_query.iter() {
+		let standing = head_trans.translation.y + FEET;
+		for (body_trans, limbs) in head_children.iter().filter_map(|child| body_query.get(*child).ok()) {
+			let body = head_trans.mul_transform(*body_trans);
+			for limb_entity in limbs.iter() {
+				let (mut trans, mut anim_pos, limb) = match limb_query.get_mut(*limb_entity) {
+					Ok(limb) => limb,
+					Err(_) => continue,
+				};
+				if let Limb::Arm(_) = limb {
+					continue;
 				}
-			} else {
-				if anim_info.anim != PlayerState::Idle {
-					anim_info.change_anim(PlayerState::Idle);
-					changed = true;
+
+				let foot = limb_end(*limb, anim_pos.pos);
+				//mid jump there's nothing to stand on
+				let lift = if physics.grounded {
+					ground_lift(&colliders, body.mul_vec3(get_leg_pivot()), body.mul_vec3(foot), standing)
+				} else {
+					0.0
+				};
+				anim_pos.lift += (lift - anim_pos.lift) * rate;
+				if anim_pos.lift.abs() < 0.001 {
+					//update_anims has already put it where the clip wants it
+					continue;
 				}
-			}
-		}
 
-		if changed {
-			for (mut anim_pos, limb, limb_sticky) in limb_query.iter_mut() {
-				if sticky == limb_sticky {
-					anim_pos.change_pos(anim_info.anim, *limb, anim_info.amount_through, 0);
+				let target = foot + body.rotation.inverse() * Vec3::new(0.0, anim_pos.lift, 0.0);
+				if let Some(reach) = reach_level_with(get_leg_pivot(), target) {
+					*trans = get_trans_from_pos(*limb, get_default_leg_pos() + reach);
 				}
 			}
 		}
 	}
 }
 
-pub fn update_anims(
-    mut player_query: Query<(&mut AnimInfo, &Sticky)>,
-    mut query: Query<(&mut Transform, &mut AnimPos, &Limb, &Sticky)>,
-    time: Res<Time>,
-) {
-    let delta = time.delta_seconds();
+//how much higher the top under a foot is than the floor the body's standing on, below zero over a ledge
+//only looks FOOT_REACH under that floor, past it a foot over a drop just hangs
+fn ground_lift(colliders: &Colliders, hip: Vec3, foot: Vec3, standing: f32) -> f32 {
+	let origin = Vec3::new(foot.x, hip.y, foot.z);
+	match colliders.raycast(origin, -Vec3::Y, hip.y - standing + FOOT_REACH) {
+		//a wall taller than the hip doesn't have a top to stand on
+		Some(hit) if hit.normal.y > 0.0 => hit.point.y - standing,
+		_ => 0.0,
+	}
+}
 
-    for (mut anim_info, player_sticky) in player_query.iter_mut() {
-        let change = anim_info.add_time(delta);
-        for (mut transform, mut anim_pos, limb, sticky) in query.iter_mut() {
-            if sticky == player_sticky {
-                if change {
-                    anim_pos.change_pos(anim_info.anim, *limb, 1.0, anim_info.index);
-                }
-                *transform = get_trans_from_pos(*limb, anim_pos.calc_curr_pos(anim_info.amount_through));
-            }
-        }
-    }
+//a limb's length from pivot that ends level with target, heading the same way round
+//anything further down than the limb is long gets it pointing straight down
+fn reach_level_with(pivot: Vec3, target: Vec3) -> Option<Vec3> {
+	let offset = target - pivot;
+	if offset.y > MINOR_HEIGHT {
+		return None;
+	}
+	let height = f32::max(offset.y, -MINOR_HEIGHT);
+	let flat = Vec3::new(offset.x, 0.0, offset.z).try_normalize().unwrap_or(Vec3::Z);
+	let across = (MINOR_HEIGHT * MINOR_HEIGHT - height * height).sqrt();
+	Some(flat * across + Vec3::new(0.0, height, 0.0))
+}
+
+//limbs hang off the body which hangs off the head, so each head only moves its own
+fn limbs_of(head_children: &Children, children_query: &Query<&Children, Without<AnimInfo>>) -> Vec<Entity> {
+    head_children.iter()
+        .filter_map(|child| children_query.get(*child).ok())
+        .flat_map(|limbs| limbs.iter().copied())
+        .collect()
 }
 
 #[derive(Component)]
 pub struct AnimInfo {
-    pub time_takes: f32,
-    pub amount_through: f32,
+    //graph picking the clips, and the state it's in
+    pub graph: String,
+    pub state: String,
+    pub time_in_state: f32,
+    //raised since the graph last looked
+    pub flags: HashSet<String>,
+    //name of the clip playing
+    pub anim: String,
+    //frame the limbs are heading towards
     pub index: usize,
-    pub anim: PlayerState,
+    pub amount_through: f32,
+    //seconds since the clip changed while it's still fading in, none once it's fully in
+    pub blend: Option<f32>,
+    //the clip changed since the last update, so the limbs fade from where they are now
+    pub changed: bool,
 }
 
 impl AnimInfo {
-    pub fn add_time(&mut self, delta_time: f32) -> bool {
-        self.amount_through = self.amount_through + delta_time / self.time_takes;
-
-        if self.amount_through > 1.0 {
-            //go to next anim
-            self.amount_through = self.amount_through - 1.0;
-            self.index = self.index + 1;
-            if self.index == self.anim.get_anim_num_frames() {
-                self.index = 0;
+    //starts off in the graph's start state once it's loaded
+    pub fn new(graph: &str) -> AnimInfo {
+        AnimInfo {
+            graph: graph.to_string(),
+            state: String::new(),
+            time_in_state: 0.0,
+            flags: HashSet::new(),
+            anim: String::new(),
+            index: 0,
+            amount_through: 0.0,
+            //nothing to fade from yet, the limbs start in the pose
+            blend: None,
+            changed: false,
+        }
+    }
+
+    //moves on by delta seconds, giving back every frame the limbs got to on the way
+    pub fn add_time(&mut self, delta_time: f32, clip: &AnimClip) -> Vec<usize> {
+        let last = clip.frames.len() - 1;
+        let mut reached = Vec::new();
+        if !clip.looping && self.index == last && self.amount_through >= 1.0 {
+            return reached;
+        }
+        self.amount_through += delta_time / clip.frames[self.index].duration;
+
+        //a long frame hitch can go past more than one, but only goes round a looping clip once
+        while self.amount_through >= 1.0 {
+            if reached.len() == clip.frames.len() {
+                self.amount_through = self.amount_through.fract();
+                break;
+            }
+            reached.push(self.index);
+            //go to next frame, or stay on the last one if it doesn't loop
+            if self.index == last && !clip.looping {
+                self.amount_through = 1.0;
+                break;
             }
-            return true;
+            let next = if self.index == last { 0 } else { self.index + 1 };
+            //what's left over carries on at the next frame's speed
+            self.amount_through = (self.amount_through - 1.0) * clip.frames[self.index].duration / clip.frames[next].duration;
+            self.index = next;
         }
-        return false;
+        reached
+    }
+
+    pub fn change_anim(&mut self, new_anim: &str) {
+        //nothing to fade from when it's only just been spawned
+        let fade = !self.anim.is_empty();
+        self.anim = new_anim.to_string();
+        self.index = 0;
+        self.amount_through = 0.0;
+        self.blend = fade.then_some(0.0);
+        self.changed = fade;
+    }
+
+    pub fn change_state(&mut self, state: &str, clip: &str) {
+        self.state = state.to_string();
+        self.time_in_state = 0.0;
+        self.change_anim(clip);
     }
 
-    pub fn change_anim(&mut self, new_anim: PlayerState) {
-    	self.index = new_anim.get_anim_num_frames() - 1;
-    	self.amount_through = 0.0;
-    	self.time_takes = new_anim.get_anim_time();
-    	self.anim = new_anim;
+    pub fn raise(&mut self, flag: &str) {
+        self.flags.insert(flag.to_string());
     }
 }
 
+//turns the body under the head rather than the head itself, the head's rotation belongs to the simulation
 pub fn spin_sticky_system(
-	mut query: Query<(&mut Transform, &AnimInfo), With<Head>>
+	head_query: Query<(&AnimInfo, &Children), With<Head>>,
+	mut body_query: Query<&mut Transform, Body>,
+	time: Res<Time>,
 ) {
-	for (mut trans, anim_info) in query.iter_mut() {
-		if anim_info.anim == PlayerState::Spin {
-			trans.rotation = trans.rotation * Quat::from_axis_angle(Vec3::Y, 0.1);
+	let turn = Quat::from_rotation_y(SPIN_SPEED * time.delta_seconds());
+	for (anim_info, children) in head_query.iter() {
+		for child in children.iter() {
+			if let Ok(mut trans) = body_query.get_mut(*child) {
+				if anim_info.anim == SPIN {
+					trans.rotation *= turn;
+				} else if trans.rotation != Quat::IDENTITY {
+					//facing forward again once it's done
+					trans.rotation = Quat::IDENTITY;
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bevy::math::const_vec3;
+
+	//a quarter of a second a frame, with a footstep on the second
+	fn clip(looping: bool) -> AnimClip {
+		let frame = |events: Vec<String>| Keyframe {
+			duration: 0.25,
+			left_arm: Vec3::ZERO,
+			right_arm: Vec3::ZERO,
+			left_leg: Vec3::ZERO,
+			right_leg: Vec3::ZERO,
+			events,
+		};
+		AnimClip {
+			name: "walk".to_string(),
+			looping,
+			easing: Easing::Linear,
+			blend_in: 0.0,
+			ground_speed: None,
+			frames: vec![frame(Vec::new()), frame(vec!["step".to_string()]), frame(Vec::new()), frame(Vec::new())],
+		}
+	}
+
+	fn steps(clip: &AnimClip, reached: &[usize]) -> usize {
+		reached.iter()
+			.filter(|index| clip.frames[**index].events.iter().any(|name| name == "step"))
+			.count()
+	}
+
+	#[test]
+	fn marker_fires_once_over_small_steps() {
+		let clip = clip(true);
+		let mut anim_info = AnimInfo::new("graph");
+		let mut fired = 0;
+		//past the footstep at half a second but not round to it again
+		for _ in 0..60 {
+			fired += steps(&clip, &anim_info.add_time(1.0 / 90.0, &clip));
+		}
+		assert_eq!(fired, 1);
+	}
+
+	#[test]
+	fn marker_fires_once_when_skipped_over() {
+		let clip = clip(true);
+		let mut anim_info = AnimInfo::new("graph");
+		//straight past three frames in one go
+		let reached = anim_info.add_time(0.8, &clip);
+		assert_eq!(reached, vec![0, 1, 2]);
+		assert_eq!(steps(&clip, &reached), 1);
+		assert_eq!(anim_info.index, 3);
+	}
+
+	#[test]
+	fn marker_fires_once_for_a_hitch_longer_than_the_clip() {
+		let clip = clip(true);
+		let mut anim_info = AnimInfo::new("graph");
+		let reached = anim_info.add_time(10.0, &clip);
+		assert_eq!(steps(&clip, &reached), 1);
+		assert!(anim_info.amount_through < 1.0);
+	}
+
+	#[test]
+	fn held_last_frame_fires_once() {
+		let clip = clip(false);
+		let mut anim_info = AnimInfo::new("graph");
+		let reached = anim_info.add_time(10.0, &clip);
+		assert_eq!(reached, vec![0, 1, 2, 3]);
+		assert_eq!(steps(&clip, &reached), 1);
+		assert!(anim_info.add_time(1.0, &clip).is_empty());
+	}
+
+	//standing at zero with the hip a bit under a leg's length above it, so a foot can go a little lower
+	const HIP: Vec3 = const_vec3!([0.0, 1.8, 0.0]);
+	const FOOT: Vec3 = const_vec3!([0.8, 0.0, 0.0]);
+	//top face at zero, ending just before the foot
+	const LEDGE: (Vec3, Vec3) = (const_vec3!([-4.5, -0.25, 0.0]), const_vec3!([10.0, 0.5, 10.0]));
+
+	fn with_walls(walls: &[(Vec3, Vec3)]) -> Colliders {
+		Colliders {
+			boxes: walls.iter().map(|&(pos, scale)| (Entity::from_raw(0), pos, scale)).collect(),
 		}
 	}
-}
\ No newline at end of file
+
+	//a box under the foot with its top at a height
+	fn top_at(height: f32) -> (Vec3, Vec3) {
+		(Vec3::new(FOOT.x, height - 0.25, 0.0), Vec3::new(1.0, 0.5, 1.0))
+	}
+
+	//where the foot ends up for a lift, and checks the leg kept its length
+	fn planted(lift: f32) -> Vec3 {
+		let reach = reach_level_with(HIP, FOOT + Vec3::new(0.0, lift, 0.0)).unwrap();
+		assert!((reach.length() - MINOR_HEIGHT).abs() < 0.001, "leg stretched to {}", reach.length());
+		HIP + reach
+	}
+
+	#[test]
+	fn foot_stays_on_flat_ground() {
+		let colliders = with_walls(&[top_at(0.0)]);
+		let lift = ground_lift(&colliders, HIP, FOOT, 0.0);
+		assert!(lift.abs() < 0.001, "moved by {} on flat ground", lift);
+	}
+
+	#[test]
+	fn foot_steps_up_onto_a_raised_top() {
+		let colliders = with_walls(&[LEDGE, top_at(0.6)]);
+		let lift = ground_lift(&colliders, HIP, FOOT, 0.0);
+		assert!((lift - 0.6).abs() < 0.001, "raised by {} instead of 0.6", lift);
+		assert!((planted(lift).y - 0.6).abs() < 0.001);
+	}
+
+	#[test]
+	fn foot_lowers_over_a_ledge() {
+		let colliders = with_walls(&[LEDGE, top_at(-0.15)]);
+		let lift = ground_lift(&colliders, HIP, FOOT, 0.0);
+		assert!((lift + 0.15).abs() < 0.001, "lowered by {} instead of 0.15", -lift);
+		assert!((planted(lift).y + 0.15).abs() < 0.001);
+	}
+
+	#[test]
+	fn foot_over_a_deep_ledge_reaches_as_far_as_the_leg_goes() {
+		//in reach of the probe but not the leg, it points straight down at it
+		let colliders = with_walls(&[LEDGE, top_at(-0.8)]);
+		let lift = ground_lift(&colliders, HIP, FOOT, 0.0);
+		assert!((lift + 0.8).abs() < 0.001);
+		assert!((planted(lift).y - (HIP.y - MINOR_HEIGHT)).abs() < 0.001);
+
+		//too far down to look for, it just hangs
+		let colliders = with_walls(&[LEDGE, top_at(-5.0)]);
+		assert_eq!(ground_lift(&colliders, HIP, FOOT, 0.0), 0.0);
+	}
+}
@@ -0,0 +1,245 @@
+use crate::anim::AnimInfo;
+use crate::physics::Physics;
+use crate::shot::{Shot, ShotHit};
+use crate::state::AppState;
+use bevy::prelude::*;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+//which clip a stick figure plays and when it changes, the graphs themselves are in GRAPHS_PATH
+
+pub const GRAPHS_PATH: &str = "stick.graphs.ron";
+
+pub const PLAYER_GRAPH: &str = "player";
+
+//flags raised by the game, each one only lasts until the graph next looks at it
+pub const HIT_FLAG: &str = "hit";
+pub const SHOOTING_FLAG: &str = "shooting";
+//raised the whole time the game's been won
+pub const WON_FLAG: &str = "won";
+
+//every graph a stick figure can follow, reloaded while the game runs when the file changes
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "b7c2e915-4d83-4a6f-8f1e-29a05d6c3b84"]
+pub struct AnimGraphs {
+	pub graphs: Vec<AnimGraph>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimGraph {
+	pub name: String,
+	//state everything following the graph starts in
+	pub start: String,
+	//checked before the current state's own, from whatever state it's in
+	#[serde(default)]
+	pub any: Vec<Transition>,
+	pub states: Vec<AnimState>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimState {
+	pub name: String,
+	pub clip: String,
+	//checked in order, the first one that holds is taken
+	#[serde(default)]
+	pub transitions: Vec<Transition>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Transition {
+	pub to: String,
+	//all of them have to hold, none at all always does
+	#[serde(default)]
+	pub when: Vec<Condition>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum Condition {
+	Grounded(bool),
+	//horizontal speed
+	SpeedAbove(f32),
+	SpeedBelow(f32),
+	//up is positive
+	VerticalAbove(f32),
+	VerticalBelow(f32),
+	//seconds since the state was entered
+	TimeInState(f32),
+	Flag(String),
+	NotFlag(String),
+}
+
+impl Condition {
+	fn holds(&self, anim_info: &AnimInfo, physics: &Physics) -> bool {
+		let speed = Vec3::new(physics.velocity.x, 0.0, physics.velocity.z).length();
+		match self {
+			Condition::Grounded(grounded) => physics.grounded == *grounded,
+			Condition::SpeedAbove(threshold) => speed > *threshold,
+			Condition::SpeedBelow(threshold) => speed < *threshold,
+			Condition::VerticalAbove(threshold) => physics.velocity.y > *threshold,
+			Condition::VerticalBelow(threshold) => physics.velocity.y < *threshold,
+			Condition::TimeInState(time) => anim_info.time_in_state >= *time,
+			Condition::Flag(flag) => anim_info.flags.contains(flag),
+			Condition::NotFlag(flag) => !anim_info.flags.contains(flag),
+		}
+	}
+}
+
+impl Transition {
+	fn holds(&self, anim_info: &AnimInfo, physics: &Physics) -> bool {
+		self.when.iter().all(|condition| condition.holds(anim_info, physics))
+	}
+}
+
+impl AnimGraphs {
+	fn validate(&self) -> Result<(), String> {
+		for (index, graph) in self.graphs.iter().enumerate() {
+			if self.graphs[..index].iter().any(|other| other.name == graph.name) {
+				return Err(format!("graph \"{}\" is defined twice", graph.name));
+			}
+			graph.validate()
+				.map_err(|err| format!("graph \"{}\" {}", graph.name, err))?;
+		}
+		Ok(())
+	}
+
+	pub fn get(&self, name: &str) -> Option<&AnimGraph> {
+		self.graphs.iter().find(|graph| graph.name == name)
+	}
+}
+
+impl AnimGraph {
+	fn validate(&self) -> Result<(), String> {
+		if self.state(&self.start).is_none() {
+			return Err(format!("starts in unknown state \"{}\"", self.start));
+		}
+		for (index, state) in self.states.iter().enumerate() {
+			if self.states[..index].iter().any(|other| other.name == state.name) {
+				return Err(format!("has state \"{}\" twice", state.name));
+			}
+		}
+		let transitions = self.any.iter()
+			.chain(self.states.iter().flat_map(|state| state.transitions.iter()));
+		for transition in transitions {
+			if self.state(&transition.to).is_none() {
+				return Err(format!("goes to unknown state \"{}\"", transition.to));
+			}
+		}
+		Ok(())
+	}
+
+	pub fn state(&self, name: &str) -> Option<&AnimState> {
+		self.states.iter().find(|state| state.name == name)
+	}
+
+	//where to go from the current state, none to stay put
+	fn next_state(&self, anim_info: &AnimInfo, physics: &Physics) -> Option<&AnimState> {
+		let current = self.state(&anim_info.state)?;
+		self.any.iter()
+			//being in the state already doesn't count as going there
+			.filter(|transition| transition.to != current.name)
+			.chain(current.transitions.iter())
+			.find(|transition| transition.holds(anim_info, physics))
+			.and_then(|transition| self.state(&transition.to))
+	}
+}
+
+#[derive(Default)]
+pub struct AnimGraphLoader;
+
+impl AssetLoader for AnimGraphLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let path = load_context.path().display().to_string();
+			let graphs: AnimGraphs = ron::de::from_bytes(bytes)
+				.map_err(|err| anyhow::anyhow!("{}:{}", path, err))?;
+			graphs.validate()
+				.map_err(|err| anyhow::anyhow!("{}: {}", path, err))?;
+			load_context.set_default_asset(LoadedAsset::new(graphs));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["graphs.ron"]
+	}
+}
+
+pub struct CurrentAnimGraphs {
+	pub handle: Handle<AnimGraphs>,
+}
+
+pub fn load_anim_graphs(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+) {
+	commands.insert_resource(CurrentAnimGraphs {
+		handle: asset_server.load(GRAPHS_PATH),
+	});
+}
+
+//shots landing and being fired, read off what the simulation's already done
+pub fn raise_anim_flags_system(
+	mut hit_events: EventReader<ShotHit>,
+	shot_query: Query<&Shot, Added<Shot>>,
+	mut query: Query<&mut AnimInfo>,
+) {
+	for hit in hit_events.iter() {
+		if let Ok(mut anim_info) = query.get_mut(hit.target) {
+			anim_info.raise(HIT_FLAG);
+		}
+	}
+	for shot in shot_query.iter() {
+		if let Ok(mut anim_info) = query.get_mut(shot.owner) {
+			anim_info.raise(SHOOTING_FLAG);
+		}
+	}
+}
+
+pub fn anim_choose_system(
+	mut query: Query<(&mut AnimInfo, &Physics)>,
+	graph_assets: Res<Assets<AnimGraphs>>,
+	current_graphs: Res<CurrentAnimGraphs>,
+	app_state: Res<State<AppState>>,
+	time: Res<Time>,
+) {
+	let graphs = match graph_assets.get(&current_graphs.handle) {
+		Some(graphs) => graphs,
+		None => return,
+	};
+	let won = *app_state.current() == AppState::Win;
+
+	for (mut anim_info, physics) in query.iter_mut() {
+		//a graph that's gone missing in a reload just leaves it playing what it was
+		let graph = match graphs.get(&anim_info.graph) {
+			Some(graph) => graph,
+			None => {
+				anim_info.flags.clear();
+				continue;
+			},
+		};
+		if won {
+			anim_info.raise(WON_FLAG);
+		}
+
+		match graph.state(&anim_info.state) {
+			Some(_) => {
+				anim_info.time_in_state += time.delta_seconds();
+				if let Some(next) = graph.next_state(&anim_info, physics) {
+					anim_info.change_state(&next.name, &next.clip);
+				}
+			},
+			//just spawned, or the state went in a reload
+			None => {
+				let start = graph.state(&graph.start).unwrap();
+				anim_info.change_state(&start.name, &start.clip);
+			},
+		}
+		anim_info.flags.clear();
+	}
+}
@@ -0,0 +1,158 @@
+use bevy::prelude::*;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use crate::aim::Accuracy;
+use crate::shot::ShotResponse;
+
+pub const ARCHETYPES_PATH: &str = "enemies.archetypes.ron";
+
+//every kind of enemy, spawned in the order they're listed
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "0f5d2c8e-3b1a-4f6e-a7d9-8c4b2e1f9a63"]
+pub struct EnemyArchetypes {
+	pub archetypes: Vec<Archetype>,
+}
+
+//copied onto each enemy so systems can read it straight off the entity
+#[derive(Component, Clone, Debug, Deserialize)]
+pub struct Archetype {
+	pub name: String,
+	pub colour: Color,
+	pub health: u16,
+	//average shots a second
+	pub fire_rate: f32,
+	pub shot_speed: f32,
+	pub shot_gravity: f32,
+	#[serde(default = "default_shot_damage")]
+	pub shot_damage: u16,
+	//what its shots do when they hit a wall
+	#[serde(default)]
+	pub shot_response: ShotResponse,
+	#[serde(default)]
+	pub accuracy: Accuracy,
+	pub movement: Movement,
+	#[serde(default)]
+	pub ability: Ability,
+	//name of the graph in GRAPHS_PATH that picks its animations
+	#[serde(default = "default_anim_graph")]
+	pub anim_graph: String,
+}
+
+fn default_shot_damage() -> u16 {
+	1
+}
+
+fn default_anim_graph() -> String {
+	"enemy".to_string()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Movement {
+	//times the normal walking push, jumps are planned at normal speed so keep it at 1 or more
+	pub speed: f32,
+	//circles the player inside this distance, chases outside it
+	pub strafe_range: f32,
+	pub flee_range: f32,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub enum Ability {
+	#[default]
+	None,
+	//heals every other enemy in range, never past their starting health
+	Heal {
+		amount: u16,
+		range: f32,
+		cooldown: f32,
+	},
+	//jumps to a random spot with floor under it
+	Teleport {
+		distance: f32,
+		cooldown: f32,
+	},
+}
+
+impl Ability {
+	pub fn cooldown(&self) -> Option<f32> {
+		match self {
+			Ability::None => None,
+			Ability::Heal { cooldown, .. } | Ability::Teleport { cooldown, .. } => Some(*cooldown),
+		}
+	}
+}
+
+impl EnemyArchetypes {
+	fn validate(&self) -> Result<(), String> {
+		if self.archetypes.is_empty() {
+			return Err("no archetypes".to_string());
+		}
+		for (index, archetype) in self.archetypes.iter().enumerate() {
+			if self.archetypes[..index].iter().any(|other| other.name == archetype.name) {
+				return Err(format!("archetype \"{}\" is defined twice", archetype.name));
+			}
+			if archetype.health == 0 {
+				return Err(format!("archetype \"{}\" has no health", archetype.name));
+			}
+			if archetype.shot_speed <= 0.0 {
+				return Err(format!("archetype \"{}\" needs a shot speed above 0", archetype.name));
+			}
+			archetype.shot_response.validate()
+				.map_err(|err| format!("archetype \"{}\" shots {}", archetype.name, err))?;
+		}
+		Ok(())
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Archetype> {
+		self.archetypes.iter().find(|archetype| archetype.name == name)
+	}
+}
+
+#[derive(Default)]
+pub struct ArchetypeLoader;
+
+impl AssetLoader for ArchetypeLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let path = load_context.path().display().to_string();
+			let archetypes: EnemyArchetypes = ron::de::from_bytes(bytes)
+				.map_err(|err| anyhow::anyhow!("{}:{}", path, err))?;
+			archetypes.validate()
+				.map_err(|err| anyhow::anyhow!("{}: {}", path, err))?;
+			load_context.set_default_asset(LoadedAsset::new(archetypes));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["archetypes.ron"]
+	}
+}
+
+pub struct ArchetypePlugin;
+
+impl Plugin for ArchetypePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_asset::<EnemyArchetypes>()
+			.init_asset_loader::<ArchetypeLoader>()
+			.add_startup_system(load_archetypes);
+	}
+}
+
+pub struct CurrentArchetypes {
+	pub handle: Handle<EnemyArchetypes>,
+}
+
+pub fn load_archetypes(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+) {
+	commands.insert_resource(CurrentArchetypes {
+		handle: asset_server.load(ARCHETYPES_PATH),
+	});
+}
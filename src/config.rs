@@ -0,0 +1,52 @@
+use std::env;
+
+const DEFAULT_TICK_RATE: f64 = 60.0;
+
+//settings picked on the command line, e.g. --tick-rate 120 --seed 42 --record match.replay
+//--level takes a path under assets, e.g. --level levels/walls.level.ron
+pub struct Config {
+	pub tick_rate: f64,
+	pub level: Option<String>,
+	pub seed: Option<u64>,
+	pub record: Option<String>,
+	pub replay: Option<String>,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Config {
+			tick_rate: DEFAULT_TICK_RATE,
+			level: None,
+			seed: None,
+			record: None,
+			replay: None,
+		}
+	}
+}
+
+impl Config {
+	pub fn from_args() -> Config {
+		let args: Vec<String> = env::args().collect();
+		let mut config = Config::default();
+
+		if let Some(tick_rate) = arg_value(&args, "--tick-rate").and_then(|value| value.parse().ok()) {
+			if tick_rate > 0.0 {
+				config.tick_rate = tick_rate;
+			}
+		}
+
+		config.level = arg_value(&args, "--level").map(String::from);
+		config.seed = arg_value(&args, "--seed").and_then(|value| value.parse().ok());
+		config.record = arg_value(&args, "--record").map(String::from);
+		config.replay = arg_value(&args, "--replay").map(String::from);
+
+		config
+	}
+}
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+	args.iter()
+		.position(|arg| arg == name)
+		.and_then(|index| args.get(index + 1))
+		.map(|value| value.as_str())
+}
@@ -0,0 +1,129 @@
+use crate::state::AppState;
+use crate::tick::{TickLabel, FixedUpdateStage, playing_tick_set};
+use bevy::input::mouse::{MouseMotion, MouseWheel};
+use std::f32::consts::PI;
+use bevy::prelude::*;
+
+//used to scale mouse movement when there's no window, e.g. headless runs
+const DEFAULT_WINDOW_WIDTH: f32 = 1280.0;
+const DEFAULT_WINDOW_HEIGHT: f32 = 720.0;
+
+//number keys pick weapons by slot, starting from 1
+const WEAPON_KEYS: [KeyCode; 9] = [
+	KeyCode::Key1, KeyCode::Key2, KeyCode::Key3,
+	KeyCode::Key4, KeyCode::Key5, KeyCode::Key6,
+	KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+];
+
+//what the player asked for during one tick, gameplay reads this rather than the devices
+//so a tick always sees the same input whether it's live, scripted or replayed
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PlayerInput {
+	pub forward: bool,
+	pub back: bool,
+	pub left: bool,
+	pub right: bool,
+	pub jump: bool,
+	//pressed since the last tick
+	pub shoot: bool,
+	//still down at this tick, for weapons that charge or keep firing
+	pub shoot_held: bool,
+	//weapon slot asked for by a number key, counting from 0
+	pub select: Option<u8>,
+	//scroll wheel notches, positive moves on to the next weapon
+	pub cycle: i8,
+	//yaw then pitch in radians
+	pub look: Vec2,
+}
+
+//device input seen since the last tick, presses are held so a frame without a tick can't drop them
+#[derive(Default)]
+pub struct InputBuffer {
+	shoot: bool,
+	select: Option<u8>,
+	cycle: i8,
+	look: Vec2,
+}
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<PlayerInput>()
+			.init_resource::<InputBuffer>()
+			.add_system_set(
+				SystemSet::on_update(AppState::Playing)
+					.with_system(gather_input_system)
+			)
+			.add_system_set_to_stage(
+				FixedUpdateStage,
+				playing_tick_set()
+					.with_system(sample_input_system.label(TickLabel::Sample))
+			);
+	}
+}
+
+pub fn gather_input_system(
+	windows: Option<Res<Windows>>,
+	mouse_input: Res<Input<MouseButton>>,
+	keyboard_input: Res<Input<KeyCode>>,
+	mut ev_motion: EventReader<MouseMotion>,
+	mut ev_wheel: EventReader<MouseWheel>,
+	mut buffer: ResMut<InputBuffer>,
+) {
+	let mut orbit = Vec2::ZERO;
+	for motion in ev_motion.iter() {
+		orbit += motion.delta;
+	}
+
+	//turn into angles here so replays don't depend on the window they were recorded in
+	let window = match &windows {
+		Some(windows) => get_primary_window_size(windows),
+		None => Vec2::new(DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT),
+	};
+	buffer.look += Vec2::new(orbit.x / window.x * PI * 2.0, -orbit.y / window.y * PI);
+
+	if mouse_input.just_pressed(MouseButton::Left) {
+		buffer.shoot = true;
+	}
+
+	if let Some(slot) = WEAPON_KEYS.iter().position(|key| keyboard_input.just_pressed(*key)) {
+		buffer.select = Some(slot as u8);
+	}
+	for wheel in ev_wheel.iter() {
+		//scrolling down goes to the next one
+		if wheel.y < 0.0 {
+			buffer.cycle = buffer.cycle.saturating_add(1);
+		} else if wheel.y > 0.0 {
+			buffer.cycle = buffer.cycle.saturating_sub(1);
+		}
+	}
+}
+
+pub fn sample_input_system(
+	keyboard_input: Res<Input<KeyCode>>,
+	mouse_input: Res<Input<MouseButton>>,
+	mut buffer: ResMut<InputBuffer>,
+	mut input: ResMut<PlayerInput>,
+) {
+	*input = PlayerInput {
+		forward: keyboard_input.pressed(KeyCode::W),
+		back: keyboard_input.pressed(KeyCode::S),
+		left: keyboard_input.pressed(KeyCode::A),
+		right: keyboard_input.pressed(KeyCode::D),
+		jump: keyboard_input.pressed(KeyCode::Space),
+		shoot: buffer.shoot,
+		shoot_held: mouse_input.pressed(MouseButton::Left),
+		select: buffer.select,
+		cycle: buffer.cycle,
+		look: buffer.look,
+	};
+	*buffer = InputBuffer::default();
+}
+
+fn get_primary_window_size(windows: &Res<Windows>) -> Vec2 {
+	match windows.get_primary() {
+		Some(window) => Vec2::new(window.width(), window.height()),
+		None => Vec2::new(DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT),
+	}
+}
@@ -1,87 +1,83 @@
 use bevy::prelude::*;
-use crate::{Sticky, Enemy,
+use crate::{Sticky, Enemy, Head, PlayerHead, EnemyHead};
+use crate::anim::{AnimPos, AnimInfo,
 	MAJOR_HEIGHT, MINOR_HEIGHT, STICK_SIZE,
-	LEFT_ARM, RIGHT_ARM, LEFT_LEG, RIGHT_LEG, 
-	AnimPos, AnimInfo, Head,
+	LEFT_ARM, RIGHT_ARM, LEFT_LEG, RIGHT_LEG,
 };
-use crate::anim::PlayerState;
+use crate::map::{Level, CurrentLevel};
+use crate::state::AppState;
+use crate::tick::{Interpolated, TickRate, TickLabel, FixedUpdateStage, playing_tick_set};
+use crate::rng::GameRng;
+use crate::physics::Physics;
+use crate::player::FallLabel;
+use crate::ai::{EnemyBrain, AiLabel};
+use crate::archetype::{Archetype, Ability};
+use std::collections::HashMap;
+use crate::placement::{place_on_platform, spacing};
+use crate::map::Collision;
+use crate::state::LOSE_Y;
 
-const ENEMY_HEALTH: u16 = 3;
-const ENEMY_NUM: u16 = 3;
+pub struct EnemyPlugin;
 
-pub struct EnemyMaterials {
-	pub red: Handle<StandardMaterial>,
-	pub green: Handle<StandardMaterial>,
-	pub blue: Handle<StandardMaterial>,
+impl Plugin for EnemyPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_system_set(
+			SystemSet::on_enter(AppState::Playing)
+				.with_system(create_enemy_assets)
+		)
+		.add_system_set_to_stage(
+			FixedUpdateStage,
+			playing_tick_set()
+				.with_system(enemy_ability_system.label(AiLabel::Ability).label(TickLabel::Ai).after(AiLabel::Move))
+				.with_system(respawn_fallen_enemies_system.after(TickLabel::Physics).after(FallLabel).before(TickLabel::Collision))
+		);
+	}
 }
 
-pub fn create_enemies(
+//meshes are shared by every enemy, materials by every enemy of an archetype,
+//made the first time one's spawned so archetypes added by a reload get one too
+pub struct EnemyAssets {
+	pub sphere: Handle<Mesh>,
+	pub main_line: Handle<Mesh>,
+	pub minor_line: Handle<Mesh>,
+	pub materials: HashMap<String, Handle<StandardMaterial>>,
+}
+
+pub fn create_enemy_assets(
 	mut commands: Commands,
 	mut meshes: ResMut<Assets<Mesh>>,
-	mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-	let sphere_handle = meshes.add(Mesh::from(shape::UVSphere::default()));
-
-	let red_material_handle = materials.add(StandardMaterial {
-        base_color: Color::rgb(1.0, 0.0, 0.0),
-        unlit: true,
-        ..StandardMaterial::default()
-    });
-
-    let green_material_handle = materials.add(StandardMaterial {
-        base_color: Color::rgb(0.0, 1.0, 0.0),
-        unlit: true,
-        ..StandardMaterial::default()
-    });
-
-    let blue_material_handle = materials.add(StandardMaterial {
-        base_color: Color::rgb(0.0, 0.0, 1.0),
-        unlit: true,
-        ..StandardMaterial::default()
-    });
-
-    let main_line_handle = meshes.add(Mesh::from(shape::Box::new(STICK_SIZE, MAJOR_HEIGHT, STICK_SIZE)));
-    let minor_line_handle = meshes.add(Mesh::from(shape::Box::new(STICK_SIZE, MINOR_HEIGHT, STICK_SIZE)));
-
-    create_enemy(
-    	&mut commands, 
-    	sphere_handle.clone(), 
-    	red_material_handle.clone(), 
-    	main_line_handle.clone(), 
-    	minor_line_handle.clone(),
-    	EnemyColour::Red,
-    );
-    create_enemy(
-    	&mut commands, 
-    	sphere_handle.clone(), 
-    	green_material_handle.clone(), 
-    	main_line_handle.clone(), 
-    	minor_line_handle.clone(),
-    	EnemyColour::Green,
-    );
-    create_enemy(
-    	&mut commands, 
-    	sphere_handle, 
-    	blue_material_handle.clone(), 
-    	main_line_handle, 
-    	minor_line_handle,
-    	EnemyColour::Blue,
-    );
-
-    commands.insert_resource(EnemyMaterials {
-    	red: red_material_handle,
-    	green: green_material_handle,
-    	blue: blue_material_handle,
-    });
-
-    commands.insert_resource(EnemyNum {
-    	number: ENEMY_NUM,
-    });
+	commands.insert_resource(EnemyAssets {
+		sphere: meshes.add(Mesh::from(shape::UVSphere::default())),
+		main_line: meshes.add(Mesh::from(shape::Box::new(STICK_SIZE, MAJOR_HEIGHT, STICK_SIZE))),
+		minor_line: meshes.add(Mesh::from(shape::Box::new(STICK_SIZE, MINOR_HEIGHT, STICK_SIZE))),
+		materials: HashMap::new(),
+	});
 }
 
-#[derive(Default)]
-pub struct EnemyNum {
-	pub number: u16,
+pub fn spawn_enemy(
+	commands: &mut Commands,
+	enemy_assets: &mut EnemyAssets,
+	materials: &mut Assets<StandardMaterial>,
+	archetype: &Archetype,
+	position: Vec3,
+) {
+	let material = enemy_assets.materials.entry(archetype.name.clone())
+		.or_insert_with(|| materials.add(StandardMaterial {
+			base_color: archetype.colour,
+			unlit: true,
+			..StandardMaterial::default()
+		}))
+		.clone();
+	create_enemy(
+		commands,
+		enemy_assets.sphere.clone(),
+		material,
+		enemy_assets.main_line.clone(),
+		enemy_assets.minor_line.clone(),
+		archetype,
+		position,
+	);
 }
 
 pub fn create_enemy(
@@ -90,28 +86,37 @@ pub fn create_enemy(
     material_handle: Handle<StandardMaterial>,
     main_line_handle: Handle<Mesh>,
     minor_line_handle: Handle<Mesh>,
-    colour: EnemyColour,
+    archetype: &Archetype,
+    position: Vec3,
 ) {
     //first, sphere head
     commands.spawn_bundle(PbrBundle {
         mesh: sphere_handle,
         material: material_handle.clone(),
-        transform: Transform::from_xyz((fastrand::f32() - 0.5) * 100.0, MAJOR_HEIGHT + MINOR_HEIGHT + 1.0, (fastrand::f32() - 0.5) * 100.0),
+        transform: Transform::from_translation(position),
         ..PbrBundle::default()
     })
     .insert(Enemy)
     .insert(Sticky::Enemy)
     .insert(Head)
+    .insert(Interpolated::new(position))
     .insert(Health {
-    	amount: ENEMY_HEALTH,
+    	amount: archetype.health,
+    	max: archetype.health,
     })
-    .insert(colour)
-    .insert(AnimInfo {
-        time_takes: 1.0,
-        amount_through: 1.0,
-        index: 0,
-        anim: PlayerState::Spin,
+    .insert(AbilityTimer {
+        remaining: archetype.ability.cooldown().unwrap_or(0.0),
     })
+    .insert(archetype.clone())
+    .insert(Physics {
+        hitboxes: vec![
+            (Vec3::new(0.0, -3.5, 0.0), Vec3::new(0.4, 6.0, 0.4)),
+        ],
+        velocity: Vec3::new(0.0, 0.0, 0.0),
+        grounded: false,
+    })
+    .insert(EnemyBrain::default())
+    .insert(AnimInfo::new(&archetype.anim_graph))
     .with_children(|parent| {
 
         parent.spawn_bundle(PbrBundle {
@@ -168,11 +173,91 @@ pub fn create_enemy(
 #[derive(Component)]
 pub struct Health {
 	pub amount: u16,
+	pub max: u16,
 }
 
+//time until the archetype's ability can be used again
 #[derive(Component)]
-pub enum EnemyColour {
-	Red,
-	Green,
-	Blue,
-}
\ No newline at end of file
+pub struct AbilityTimer {
+	pub remaining: f32,
+}
+//enemies walk now so they can fall off too, put them back somewhere they started
+pub fn respawn_fallen_enemies_system(
+	mut query: Query<(Entity, &mut Transform, &mut Interpolated, &mut Physics), EnemyHead>,
+	player_query: Query<&Transform, PlayerHead>,
+	collision_query: Query<&Transform, (With<Collision>, Without<Enemy>)>,
+	levels: Res<Assets<Level>>,
+	current_level: Res<CurrentLevel>,
+	mut rng: ResMut<GameRng>,
+) {
+	let level = levels.get(&current_level.handle).unwrap();
+	let walls: Vec<(Vec3, Vec3)> = collision_query.iter()
+		.map(|trans| (trans.translation, trans.scale))
+		.collect();
+	let enemies: Vec<(Entity, Vec3)> = query.iter()
+		.map(|(entity, trans, _, _)| (entity, trans.translation))
+		.collect();
+
+	for (entity, mut trans, mut interpolated, mut physics) in query.iter_mut() {
+		if trans.translation.y < LOSE_Y {
+			let avoid = spacing(
+				player_query.iter().map(|trans| trans.translation),
+				enemies.iter().filter(|(other, _)| *other != entity).map(|(_, point)| *point),
+			);
+			let position = place_on_platform(&walls, &avoid, None, &mut rng)
+				.unwrap_or_else(|| level.enemy_spawns[rng.usize(level.enemy_spawns.len())]);
+			interpolated.snap(&mut trans, position);
+			physics.velocity = Vec3::ZERO;
+		}
+	}
+}
+
+pub fn enemy_ability_system(
+	mut query: Query<(Entity, &mut Transform, &mut Interpolated, &Archetype, &mut AbilityTimer, &mut Health), EnemyHead>,
+	player_query: Query<&Transform, PlayerHead>,
+	collision_query: Query<&Transform, (With<Collision>, Without<Enemy>)>,
+	tick_rate: Res<TickRate>,
+	mut rng: ResMut<GameRng>,
+) {
+	let walls: Vec<(Vec3, Vec3)> = collision_query.iter()
+		.map(|trans| (trans.translation, trans.scale))
+		.collect();
+	let enemies: Vec<(Entity, Vec3)> = query.iter()
+		.map(|(entity, trans, _, _, _, _)| (entity, trans.translation))
+		.collect();
+
+	//healer, where it is, how far it reaches and by how much
+	let mut heals = Vec::new();
+	for (entity, mut trans, mut interpolated, archetype, mut timer, _) in query.iter_mut() {
+		timer.remaining -= tick_rate.step();
+		if timer.remaining > 0.0 {
+			continue;
+		}
+
+		match archetype.ability {
+			Ability::None => continue,
+			Ability::Heal { amount, range, .. } => {
+				heals.push((entity, trans.translation, range, amount));
+			},
+			Ability::Teleport { distance, .. } => {
+				//stays put if there's nowhere free close enough
+				let avoid = spacing(
+					player_query.iter().map(|trans| trans.translation),
+					enemies.iter().filter(|(other, _)| *other != entity).map(|(_, point)| *point),
+				);
+				if let Some(position) = place_on_platform(&walls, &avoid, Some((trans.translation, distance)), &mut rng) {
+					interpolated.snap(&mut trans, position);
+				}
+			},
+		}
+		timer.remaining = archetype.ability.cooldown().unwrap_or(0.0);
+	}
+
+	for (healer, position, range, amount) in heals {
+		for (entity, trans, _, _, _, mut health) in query.iter_mut() {
+			if entity != healer && trans.translation.distance(position) <= range {
+				health.amount = u16::min(health.amount.saturating_add(amount), health.max);
+			}
+		}
+	}
+}
@@ -0,0 +1,166 @@
+use crate::map::{MapPlugin, add_light, add_scene};
+use crate::anim::AnimPlugin;
+use crate::physics::PhysicsPlugin;
+use crate::player::{PlayerPlugin, cursor_grab_system};
+use crate::state::{AppState, GameStatePlugin, game_over_system, game_win_system};
+use crate::enemy::EnemyPlugin;
+use crate::shot::ShotPlugin;
+use crate::tick::{TickPlugin, TickRate};
+use crate::rng::RngPlugin;
+use crate::controls::ControlsPlugin;
+use crate::replay::ReplayPlugin;
+use crate::ai::AiPlugin;
+use crate::nav::NavPlugin;
+use crate::archetype::ArchetypePlugin;
+use crate::wave::WavePlugin;
+use crate::weapon::WeaponPlugin;
+use bevy::prelude::*;
+use bevy::app::{PluginGroupBuilder, ScheduleRunnerPlugin};
+use bevy::asset::AssetPlugin;
+use bevy::core::CorePlugin;
+use bevy::input::InputPlugin;
+use bevy::transform::TransformPlugin;
+
+pub mod anim;
+pub mod anim_graph;
+pub mod map;
+pub mod state;
+pub mod spherical;
+pub mod enemy;
+pub mod shot;
+pub mod physics;
+pub mod tick;
+pub mod config;
+pub mod player;
+pub mod rng;
+pub mod controls;
+pub mod replay;
+pub mod ai;
+pub mod nav;
+pub mod archetype;
+pub mod wave;
+pub mod placement;
+pub mod aim;
+pub mod weapon;
+
+//the game itself, needs no window or renderer so it can run headless
+//tick goes first as the others add to its stage
+pub struct SimulationPlugins;
+
+impl PluginGroup for SimulationPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group.add(TickPlugin);
+        group.add(RngPlugin);
+        group.add(ControlsPlugin);
+        group.add(ReplayPlugin);
+        group.add(GameStatePlugin);
+        group.add(MapPlugin);
+        group.add(ArchetypePlugin);
+        group.add(WeaponPlugin);
+        group.add(PlayerPlugin);
+        group.add(EnemyPlugin);
+        group.add(WavePlugin);
+        group.add(NavPlugin);
+        group.add(AiPlugin);
+        group.add(ShotPlugin);
+        group.add(PhysicsPlugin);
+        group.add(AnimPlugin);
+    }
+}
+
+//the window side of the game, lighting, cursor grabbing and the end screens
+pub struct ClientPlugin;
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(watch_for_asset_changes)
+            .add_system_set(
+                SystemSet::on_enter(AppState::Playing)
+                    .with_system(add_light)
+                    .with_system(add_scene)
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(cursor_grab_system)
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver)
+                    .with_system(game_over_system)
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Win)
+                    .with_system(game_win_system)
+            );
+    }
+}
+
+//so animation clips and the like can be tweaked while playing
+fn watch_for_asset_changes(
+    asset_server: Res<AssetServer>,
+) {
+    if let Err(err) = asset_server.watch_for_changes() {
+        warn!("assets won't reload when changed: {:?}", err);
+    }
+}
+
+//what the simulation needs from bevy when there's no window,
+//input is read from the usual resources so tests can press keys on them directly
+pub struct HeadlessPlugins;
+
+impl PluginGroup for HeadlessPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group.add(CorePlugin);
+        group.add(ScheduleRunnerPlugin);
+        group.add(TransformPlugin);
+        group.add(InputPlugin);
+        group.add(AssetPlugin);
+        group.add(HeadlessAssetsPlugin);
+    }
+}
+
+//the renderer normally registers these
+struct HeadlessAssetsPlugin;
+
+impl Plugin for HeadlessAssetsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>();
+    }
+}
+
+//an app that steps the simulation once per update, for tests and tools
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.insert_resource(TickRate {
+            lockstep: true,
+            ..TickRate::default()
+        })
+        .add_plugins(HeadlessPlugins)
+        .add_plugins(SimulationPlugins);
+    app
+}
+
+#[derive(Component)]
+pub struct Player;
+
+#[derive(Component)]
+pub struct Enemy;
+
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sticky {
+    Player,
+    Enemy,
+}
+
+#[derive(Component)]
+pub struct Head;
+
+//the part of the player or an enemy that moves and thinks, the rest of the body follows it,
+//each leaves out the other so a system can borrow both
+pub type PlayerHead = (With<Player>, With<Head>, Without<Enemy>);
+pub type EnemyHead = (With<Enemy>, With<Head>, Without<Player>);
+
+pub fn rotate_around(transform: &mut Transform, point: Vec3, rotation: Quat) {
+    transform.translation = point + rotation * (transform.translation - point);
+    transform.rotation *= rotation;
+}
@@ -1,61 +1,322 @@
 use bevy::prelude::*;
-use crate::state::AppState;
-
-
-//walls are defined as x, y, z, x_scale, y_scale, z_scale
-const NUM_WALLS: usize = 18;
-const WALLS: [f32; NUM_WALLS * 6] = [
-	0.0, -0.25, 0.0, 21.0, 0.5, 21.0,
-	13.2, -0.25, -20.4, 11.0, 0.5, 11.0,
-	-24.2, -0.25, -1.2, 11.0, 0.5, 11.0,
-	-16.6, -0.25, 17.6, 11.0, 0.5, 11.0,
-	12.8, -0.25, 18.3, 11.0, 0.5, 11.0,
-
-	32.5, -0.25, 1.7, 11.0, 0.5, 11.0,
-	37.6, -0.25, -17.4, 11.0, 0.5, 11.0,
-	26.0, -0.25, -43.8, 11.0, 0.5, 11.0,
-	-6.5, -0.25, -39.5, 11.0, 0.5, 11.0,
-	-28.0, -0.25, -21.3, 11.0, 0.5, 11.0,
-
-	-41.2, -0.25, -43.1, 11.0, 0.5, 11.0,
-	-53.5, -0.25, -13.7, 11.0, 0.5, 11.0,
-	-44.4, -0.25, 12.5, 11.0, 0.5, 11.0,
-	-28.9, -0.25, 36.7, 11.0, 0.5, 11.0,
-	0.4, -0.25, 36.7, 11.0, 0.5, 11.0,
-
-	32.9, -0.25, 33.5, 11.0, 0.5, 11.0,
-	4.9, 1.2, -29.5, 4.2, 0.5, 4.2,
-	-16.8, 1.2, -30.1, 4.2, 0.5, 4.2,
-];
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset, LoadState};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use bevy::ecs::system::SystemParam;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use serde::Deserialize;
+use crate::state::{AppState, LoseRule};
+use crate::archetype::{CurrentArchetypes, ARCHETYPES_PATH};
+use crate::weapon::{CurrentWeapons, WEAPONS_PATH};
+use crate::anim::{CurrentAnimClips, ANIMS_PATH};
+use crate::anim_graph::{CurrentAnimGraphs, GRAPHS_PATH};
+use crate::wave::{Wave, Endless};
+use crate::shot::FriendlyFire;
+
+pub const DEFAULT_LEVEL_PATH: &str = "levels/arena.level.ron";
 
 #[derive(Component)]
 pub struct Collision;
 
+//walls are boxes given by their centre and size
+//a level can instead point at a gltf scene, then the walls come from its meshes
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "6b0c3a57-0f7e-4c8e-9d43-5a3e1f1b7c21"]
+pub struct Level {
+	#[serde(default)]
+	pub materials: HashMap<String, LevelMaterial>,
+	#[serde(default)]
+	pub platforms: Vec<Platform>,
+	#[serde(default)]
+	pub scene: Option<String>,
+	pub player_spawn: Vec3,
+	#[serde(default = "default_player_health")]
+	pub player_health: u16,
+	#[serde(default)]
+	pub lose: LoseRule,
+	#[serde(default)]
+	pub friendly_fire: FriendlyFire,
+	pub enemy_spawns: Vec<Vec3>,
+	pub waves: Vec<Wave>,
+	#[serde(default)]
+	pub endless: Option<Endless>,
+	#[serde(skip)]
+	pub scene_colliders: Vec<(Vec3, Vec3)>,
+}
+
+fn default_player_health() -> u16 {
+	5
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LevelMaterial {
+	pub colour: Color,
+	#[serde(default)]
+	pub unlit: bool,
+}
+
+//collisions are axis aligned boxes, so validate turns down any platform that is actually rotated
+#[derive(Debug, Deserialize)]
+pub struct Platform {
+	pub translation: Vec3,
+	#[serde(default)]
+	pub rotation: Quat,
+	pub scale: Vec3,
+	pub material: String,
+}
+
+impl Level {
+	fn validate(&self) -> Result<(), String> {
+		for (index, platform) in self.platforms.iter().enumerate() {
+			if !self.materials.contains_key(&platform.material) {
+				return Err(format!("platform {} uses unknown material \"{}\"", index, platform.material));
+			}
+			if !platform.rotation.abs_diff_eq(Quat::IDENTITY, 1e-6) {
+				return Err(format!("platform {} is rotated, only axis aligned platforms can be collided with", index));
+			}
+		}
+		if self.platforms.is_empty() && self.scene_colliders.is_empty() {
+			return Err("level has no platforms".to_string());
+		}
+		if self.lose.uses_health() && self.player_health == 0 {
+			return Err("player starts with no health".to_string());
+		}
+		if self.enemy_spawns.is_empty() {
+			return Err("level has no enemy spawns".to_string());
+		}
+		if self.waves.is_empty() {
+			return Err("level has no waves".to_string());
+		}
+		for (index, wave) in self.waves.iter().enumerate() {
+			wave.validate()
+				.map_err(|err| format!("wave {} {}", index + 1, err))?;
+		}
+		Ok(())
+	}
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let path = load_context.path().display().to_string();
+			//ron errors carry the line and column so pass them through with the file
+			let mut level: Level = ron::de::from_bytes(bytes)
+				.map_err(|err| anyhow::anyhow!("{}:{}", path, err))?;
+			if let Some(scene) = &level.scene {
+				let scene_bytes = load_context.read_asset_bytes(scene).await?;
+				level.scene_colliders = colliders_from_gltf(&scene_bytes)
+					.map_err(|err| anyhow::anyhow!("{}: {}", scene, err))?;
+			}
+			level.validate()
+				.map_err(|err| anyhow::anyhow!("{}: {}", path, err))?;
+			load_context.set_default_asset(LoadedAsset::new(level));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["level.ron"]
+	}
+}
+
+//gives the centre and size of the world space bounding box of every mesh in the default scene
+pub fn colliders_from_gltf(bytes: &[u8]) -> Result<Vec<(Vec3, Vec3)>, gltf::Error> {
+	let gltf = gltf::Gltf::from_slice(bytes)?;
+	let scene = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+		Some(scene) => scene,
+		None => return Ok(Vec::new()),
+	};
+
+	let mut colliders = Vec::new();
+	for node in scene.nodes() {
+		add_node_colliders(&node, Mat4::IDENTITY, &mut colliders);
+	}
+	Ok(colliders)
+}
+
+fn add_node_colliders(node: &gltf::Node, parent: Mat4, colliders: &mut Vec<(Vec3, Vec3)>) {
+	let matrix = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+	if let Some(mesh) = node.mesh() {
+		for primitive in mesh.primitives() {
+			let bounds = primitive.bounding_box();
+			let (min, max) = (Vec3::from(bounds.min), Vec3::from(bounds.max));
+
+			//transform every corner so rotated nodes still get a box that covers them
+			let mut world_min = Vec3::splat(f32::MAX);
+			let mut world_max = Vec3::splat(f32::MIN);
+			for corner in 0..8 {
+				let local = Vec3::new(
+					if corner & 1 == 0 { min.x } else { max.x },
+					if corner & 2 == 0 { min.y } else { max.y },
+					if corner & 4 == 0 { min.z } else { max.z },
+				);
+				let world = matrix.transform_point3(local);
+				world_min = world_min.min(world);
+				world_max = world_max.max(world);
+			}
+			colliders.push(((world_min + world_max) / 2.0, world_max - world_min));
+		}
+	}
+
+	for child in node.children() {
+		add_node_colliders(&child, matrix, colliders);
+	}
+}
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<LevelSelect>()
+			.add_asset::<Level>()
+			.init_asset_loader::<LevelLoader>()
+			.add_startup_system(load_level)
+			.add_system_set(
+				SystemSet::on_update(AppState::Loading)
+					.with_system(on_load_map)
+			)
+			.add_system_set(
+				SystemSet::on_enter(AppState::Playing)
+					.with_system(add_map)
+			);
+	}
+}
+
+//which level file to play, insert one before the plugins to pick something other than the default
+pub struct LevelSelect {
+	pub path: String,
+}
+
+impl Default for LevelSelect {
+	fn default() -> Self {
+		LevelSelect {
+			path: DEFAULT_LEVEL_PATH.to_string(),
+		}
+	}
+}
+
+pub struct CurrentLevel {
+	pub handle: Handle<Level>,
+	pub path: String,
+}
+
+pub fn load_level(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	level_select: Res<LevelSelect>,
+) {
+	commands.insert_resource(CurrentLevel {
+		handle: asset_server.load(level_select.path.as_str()),
+		path: level_select.path.clone(),
+	});
+}
+
+//everything that has to be loaded before the game can start
+#[derive(SystemParam)]
+pub struct GameAssets<'w, 's> {
+	level: Res<'w, CurrentLevel>,
+	archetypes: Res<'w, CurrentArchetypes>,
+	weapons: Res<'w, CurrentWeapons>,
+	clips: Res<'w, CurrentAnimClips>,
+	graphs: Res<'w, CurrentAnimGraphs>,
+	#[system_param(ignore)]
+	marker: PhantomData<&'s ()>,
+}
 
 pub fn on_load_map(
 	mut app_state: ResMut<State<AppState>>,
+	asset_server: Res<AssetServer>,
+	game_assets: GameAssets,
+	mut reported: Local<bool>,
 ) {
-	app_state.set(AppState::Playing).unwrap();
+	let assets = [
+		(game_assets.level.path.as_str(), game_assets.level.handle.id),
+		(ARCHETYPES_PATH, game_assets.archetypes.handle.id),
+		(WEAPONS_PATH, game_assets.weapons.handle.id),
+		(ANIMS_PATH, game_assets.clips.handle.id),
+		(GRAPHS_PATH, game_assets.graphs.handle.id),
+	];
+
+	match asset_server.get_group_load_state(assets.iter().map(|(_, id)| *id)) {
+		LoadState::Loaded => {
+			app_state.set(AppState::Playing).unwrap();
+		},
+		//the asset server already logged why, stay here rather than play without them
+		LoadState::Failed if !*reported => {
+			for (path, id) in assets {
+				if asset_server.get_load_state(id) == LoadState::Failed {
+					error!("could not load {}", path);
+				}
+			}
+			*reported = true;
+		},
+		_ => {},
+	}
+}
+
+//the look of a gltf level, only the client has a renderer to draw it with
+pub fn add_scene(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+	levels: Res<Assets<Level>>,
+	current_level: Res<CurrentLevel>,
+) {
+	let level = levels.get(&current_level.handle).unwrap();
+	if let Some(scene) = &level.scene {
+		commands.spawn_scene(asset_server.load(format!("{}#Scene0", scene).as_str()));
+	}
 }
 
 pub fn add_map(
 	mut commands: Commands,
-	mut meshes: ResMut<Assets<Mesh>>, 
+	mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+	levels: Res<Assets<Level>>,
+	current_level: Res<CurrentLevel>,
 ) {
+	let level = levels.get(&current_level.handle).unwrap();
+
+	if level.scene.is_some() {
+		//add_scene draws it, these are just the boxes to collide with
+		for &(translation, scale) in level.scene_colliders.iter() {
+			commands.spawn_bundle((
+				Transform::from_translation(translation).with_scale(scale),
+				GlobalTransform::default(),
+				Collision,
+			));
+		}
+	}
+
 	let wall_mesh = meshes.add(Mesh::from(shape::Box::new(1.0, 1.0, 1.0)));
-	let wall_material = materials.add(StandardMaterial {
-		base_color: Color::rgb(1.0, 1.0, 1.0),
-		..StandardMaterial::default()
-	});
-	for wall in WALLS.chunks(6) {
+	let level_materials: HashMap<&str, Handle<StandardMaterial>> = level.materials.iter()
+		.map(|(name, material)| {
+			let handle = materials.add(StandardMaterial {
+				base_color: material.colour,
+				unlit: material.unlit,
+				..StandardMaterial::default()
+			});
+			(name.as_str(), handle)
+		})
+		.collect();
+
+	for platform in level.platforms.iter() {
 
-		let transform = Transform::from_xyz(wall[0], wall[1], wall[2])
-			.with_scale(Vec3::new(wall[3], wall[4], wall[5]));
+		let transform = Transform::from_translation(platform.translation)
+			.with_rotation(platform.rotation)
+			.with_scale(platform.scale);
 
 		commands.spawn_bundle(PbrBundle {
 			mesh: wall_mesh.clone(),
-			material: wall_material.clone(),
+			material: level_materials[platform.material.as_str()].clone(),
 			transform,
 			..PbrBundle::default()
 		})
@@ -75,4 +336,28 @@ pub fn add_light(
 		},
 		brightness: 1.0,
 	});
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn gltf_colliders_cover_each_mesh() {
+		let bytes = std::fs::read("assets/walls.gltf").unwrap();
+		let colliders = colliders_from_gltf(&bytes).unwrap();
+
+		//walls.blend is a single floor, a unit cube scaled out and dropped half its height
+		assert_eq!(colliders.len(), 1);
+		let (centre, size) = colliders[0];
+		assert!(centre.abs_diff_eq(Vec3::new(0.0, -0.25, 0.0), 1e-4), "centre {}", centre);
+		assert!(size.abs_diff_eq(Vec3::new(11.041_24, 0.5, 11.041_24), 1e-4), "size {}", size);
+	}
+
+	#[test]
+	fn gltf_level_parses() {
+		let bytes = std::fs::read("assets/levels/walls.level.ron").unwrap();
+		let level: Level = ron::de::from_bytes(&bytes).unwrap();
+		assert_eq!(level.scene.as_deref(), Some("walls.gltf"));
+	}
+}
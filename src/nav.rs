@@ -0,0 +1,297 @@
+use crate::map::Collision;
+use crate::physics::GRAVITY;
+use crate::ai::ENEMY_JUMP;
+use crate::tick::{TickLabel, FixedUpdateStage, playing_tick_set};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+use bevy::prelude::*;
+
+//a jump peaks a bit under three units up so anything higher than this can't be climbed
+pub const JUMP_RISE: f32 = 2.0;
+//how far down a body will happily drop to get somewhere
+const MAX_FALL: f32 = 20.0;
+//platforms this close at about the same height can just be walked between
+const STEP_GAP: f32 = 0.1;
+const STEP_HEIGHT: f32 = 0.5;
+//extra cost so a walk is preferred over a jump of the same length
+const JUMP_COST: f32 = 2.0;
+//narrower than a body and it can't be stood on
+const MIN_WIDTH: f32 = 0.4;
+
+pub struct NavPlugin;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct NavLabel;
+
+impl Plugin for NavPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<NavGraph>()
+			.add_system_set_to_stage(
+				FixedUpdateStage,
+				playing_tick_set()
+					.with_system(build_nav_graph_system.label(NavLabel).before(TickLabel::Input))
+			);
+	}
+}
+
+//roughly how far a running jump carries for a body pushed at speed a second,
+//ground speed settles at about one and a half times the push each tick
+pub fn jump_reach(speed: f32, step: f32) -> f32 {
+	let airtime = 2.0 * ENEMY_JUMP / GRAVITY;
+	airtime * speed * step * 1.5
+}
+
+//the top of a collision box, as something that can be stood on
+#[derive(Clone, Debug)]
+pub struct NavNode {
+	//corners of the top face on the x and z axes
+	pub min: Vec2,
+	pub max: Vec2,
+	pub top: f32,
+}
+
+impl NavNode {
+	pub fn centre(&self) -> Vec3 {
+		let centre = (self.min + self.max) / 2.0;
+		Vec3::new(centre.x, self.top, centre.y)
+	}
+
+	fn contains(&self, point: Vec3) -> bool {
+		point.x >= self.min.x && point.x <= self.max.x && point.z >= self.min.y && point.z <= self.max.y
+	}
+
+	//horizontal distance from a point to the nearest part of the top face
+	fn distance_to(&self, point: Vec3) -> f32 {
+		let clamped = Vec2::new(point.x, point.z).clamp(self.min, self.max);
+		clamped.distance(Vec2::new(point.x, point.z))
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+	Walk,
+	Jump,
+}
+
+//a way from one node to another, leave from from_point and arrive at to_point
+#[derive(Clone, Debug)]
+pub struct NavEdge {
+	pub to: usize,
+	pub kind: EdgeKind,
+	pub from_point: Vec3,
+	pub to_point: Vec3,
+	//horizontal distance a jump has to carry, bodies that can't make it leave the edge out
+	pub gap: f32,
+	pub cost: f32,
+}
+
+#[derive(Default)]
+pub struct NavGraph {
+	pub nodes: Vec<NavNode>,
+	//edges leaving each node, indexed the same as nodes
+	pub edges: Vec<Vec<NavEdge>>,
+}
+
+impl NavGraph {
+	//walls are centre and size like everywhere else
+	pub fn build(walls: &[(Vec3, Vec3)]) -> Self {
+		let nodes: Vec<NavNode> = walls.iter()
+			.filter(|(_, scale)| scale.x >= MIN_WIDTH && scale.z >= MIN_WIDTH)
+			.map(|&(pos, scale)| NavNode {
+				min: Vec2::new(pos.x - scale.x/2.0, pos.z - scale.z/2.0),
+				max: Vec2::new(pos.x + scale.x/2.0, pos.z + scale.z/2.0),
+				top: pos.y + scale.y/2.0,
+			})
+			.collect();
+
+		let edges = nodes.iter()
+			.enumerate()
+			.map(|(from_index, from)| {
+				nodes.iter()
+					.enumerate()
+					.filter(|(to, _)| *to != from_index)
+					.filter_map(|(to, node)| link(from, node, to))
+					.collect()
+			})
+			.collect();
+
+		NavGraph {
+			nodes,
+			edges,
+		}
+	}
+
+	//the node being stood on, the highest one under the feet
+	pub fn node_at(&self, feet: Vec3) -> Option<usize> {
+		self.nodes.iter()
+			.enumerate()
+			.filter(|(_, node)| node.contains(feet) && node.top <= feet.y + STEP_HEIGHT)
+			.max_by(|(_, a), (_, b)| a.top.total_cmp(&b.top))
+			.map(|(index, _)| index)
+	}
+
+	//the node a point is over or closest to, for targets that are in the air
+	pub fn nearest_node(&self, point: Vec3) -> Option<usize> {
+		self.node_at(point).or_else(|| {
+			self.nodes.iter()
+				.enumerate()
+				.filter(|(_, node)| node.top <= point.y + STEP_HEIGHT)
+				.min_by(|(_, a), (_, b)| a.distance_to(point).total_cmp(&b.distance_to(point)))
+				.map(|(index, _)| index)
+		})
+	}
+
+	//a* between two nodes for a body that jumps as far as reach, the edges to follow in order
+	pub fn find_path(&self, start: usize, goal: usize, reach: f32) -> Option<Vec<NavEdge>> {
+		let goal_centre = self.nodes[goal].centre();
+		let mut best_cost = vec![f32::MAX; self.nodes.len()];
+		//the node and edge each node was reached through
+		let mut came_from: Vec<Option<(usize, usize)>> = vec![None; self.nodes.len()];
+		let mut open = BinaryHeap::new();
+
+		best_cost[start] = 0.0;
+		open.push(OpenNode {
+			estimate: self.nodes[start].centre().distance(goal_centre),
+			node: start,
+		});
+
+		while let Some(OpenNode { estimate, node }) = open.pop() {
+			if node == goal {
+				let mut path = Vec::new();
+				let mut current = goal;
+				while let Some((previous, edge)) = came_from[current] {
+					path.push(self.edges[previous][edge].clone());
+					current = previous;
+				}
+				path.reverse();
+				return Some(path);
+			}
+			//already found a cheaper way here
+			if estimate - self.nodes[node].centre().distance(goal_centre) > best_cost[node] {
+				continue;
+			}
+
+			for (index, edge) in self.edges[node].iter().enumerate() {
+				if edge.kind == EdgeKind::Jump && edge.gap > reach {
+					continue;
+				}
+				let cost = best_cost[node] + edge.cost;
+				if cost < best_cost[edge.to] {
+					best_cost[edge.to] = cost;
+					came_from[edge.to] = Some((node, index));
+					open.push(OpenNode {
+						estimate: cost + self.nodes[edge.to].centre().distance(goal_centre),
+						node: edge.to,
+					});
+				}
+			}
+		}
+		None
+	}
+
+	//path between two positions of feet, empty when they're already on the same node
+	pub fn route(&self, from: Vec3, to: Vec3, reach: f32) -> Option<Vec<NavEdge>> {
+		let start = self.node_at(from)?;
+		let goal = self.nearest_node(to)?;
+		self.find_path(start, goal, reach)
+	}
+}
+
+impl fmt::Display for NavGraph {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "nav graph, {} nodes", self.nodes.len())?;
+		for (index, node) in self.nodes.iter().enumerate() {
+			writeln!(f, "{}: top {:.2} from ({:.2}, {:.2}) to ({:.2}, {:.2})",
+				index, node.top, node.min.x, node.min.y, node.max.x, node.max.y)?;
+			for edge in self.edges[index].iter() {
+				writeln!(f, "    {:?} to {} cost {:.2}", edge.kind, edge.to, edge.cost)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+//edge from one node to another if it can be walked or jumped, how far is left to whoever follows it
+fn link(from: &NavNode, to: &NavNode, to_index: usize) -> Option<NavEdge> {
+	let (from_x, to_x) = closest_between(from.min.x, from.max.x, to.min.x, to.max.x);
+	let (from_z, to_z) = closest_between(from.min.y, from.max.y, to.min.y, to.max.y);
+	let from_point = Vec3::new(from_x, from.top, from_z);
+	let to_point = Vec3::new(to_x, to.top, to_z);
+
+	let gap = Vec2::new(to_x - from_x, to_z - from_z).length();
+	let rise = to.top - from.top;
+
+	let kind = if gap <= STEP_GAP && rise.abs() <= STEP_HEIGHT {
+		EdgeKind::Walk
+	} else if (-MAX_FALL..=JUMP_RISE).contains(&rise) {
+		EdgeKind::Jump
+	} else {
+		return None;
+	};
+
+	//walk in from the middle, across, then on to the middle of the next one
+	let mut cost = from.centre().distance(from_point) + from_point.distance(to_point) + to_point.distance(to.centre());
+	if kind == EdgeKind::Jump {
+		cost += JUMP_COST;
+	}
+
+	Some(NavEdge {
+		to: to_index,
+		kind,
+		from_point,
+		to_point,
+		gap,
+		cost,
+	})
+}
+
+//nearest pair of points between two ranges on one axis, the middle of the overlap if they overlap
+fn closest_between(a_min: f32, a_max: f32, b_min: f32, b_max: f32) -> (f32, f32) {
+	if a_max < b_min {
+		(a_max, b_min)
+	} else if b_max < a_min {
+		(a_min, b_max)
+	} else {
+		let middle = (f32::max(a_min, b_min) + f32::min(a_max, b_max)) / 2.0;
+		(middle, middle)
+	}
+}
+
+#[derive(PartialEq)]
+struct OpenNode {
+	estimate: f32,
+	node: usize,
+}
+
+impl Eq for OpenNode {}
+
+//reversed so the heap pops the cheapest first
+impl Ord for OpenNode {
+	fn cmp(&self, other: &Self) -> Ordering {
+		other.estimate.total_cmp(&self.estimate)
+	}
+}
+
+impl PartialOrd for OpenNode {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+//rebuilt whenever the map is spawned, a restart brings new collision entities
+pub fn build_nav_graph_system(
+	mut nav_graph: ResMut<NavGraph>,
+	added_query: Query<(), Added<Collision>>,
+	collision_query: Query<&Transform, With<Collision>>,
+) {
+	if added_query.is_empty() {
+		return;
+	}
+
+	let walls: Vec<(Vec3, Vec3)> = collision_query.iter()
+		.map(|trans| (trans.translation, trans.scale))
+		.collect();
+	*nav_graph = NavGraph::build(&walls);
+	debug!("{}", *nav_graph);
+}
@@ -0,0 +1,379 @@
+use crate::map::Collision;
+use crate::tick::{TickRate, TickLabel, FixedUpdateStage, playing_tick_set};
+use bevy::prelude::*;
+
+pub const GRAVITY: f32 = 12.0;
+//movement was tuned at this many ticks a second, anything done every tick is scaled from it
+const TUNED_TICK_RATE: f32 = 60.0;
+//share of its speed a body on the ground keeps each tick at the tuned rate
+const GROUND_FRICTION: f32 = 0.6;
+
+//how many times a body can hit something and slide along it in one frame
+const MAX_SWEEPS: usize = 4;
+//gap left between a body and what it hit so the next sweep doesn't start inside it
+const SKIN: f32 = 0.001;
+
+pub struct PhysicsPlugin;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct CollidersLabel;
+
+impl Plugin for PhysicsPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<Colliders>()
+			.add_system_set_to_stage(
+			FixedUpdateStage,
+			playing_tick_set()
+				.with_system(update_colliders_system.label(CollidersLabel).before(TickLabel::Input))
+				.with_system(gravity_system.label(TickLabel::Gravity).after(TickLabel::Ai))
+				.with_system(physics_system.label(TickLabel::Physics).after(TickLabel::Gravity))
+		);
+	}
+}
+
+#[derive(Component)]
+pub struct Physics {
+	pub hitboxes: Vec<(Vec3, Vec3)>,
+	pub velocity: Vec3,
+	pub grounded: bool,
+}
+
+pub fn update_colliders_system(
+	mut colliders: ResMut<Colliders>,
+	added_query: Query<(), Added<Collision>>,
+	collision_query: Query<(Entity, &Transform), With<Collision>>,
+) {
+	if added_query.is_empty() {
+		return;
+	}
+	colliders.boxes = collision_query.iter()
+		.map(|(entity, trans)| (entity, trans.translation, trans.scale))
+		.collect();
+}
+
+pub fn gravity_system(
+	mut query: Query<&mut Physics>,
+	tick_rate: Res<TickRate>,
+) {
+	for mut physics in query.iter_mut() {
+		physics.velocity.y -= GRAVITY * tick_rate.step();
+	}
+}
+
+pub fn physics_system(
+	mut query: Query<(&mut Transform, &mut Physics), Without<Collision>>,
+	collision_query: Query<&Transform, With<Collision>>,
+	tick_rate: Res<TickRate>,
+) {
+	let delta = tick_rate.step();
+	let friction = GROUND_FRICTION.powf(delta * TUNED_TICK_RATE);
+	let walls: Vec<(Vec3, Vec3)> = collision_query.iter()
+		.map(|trans| (trans.translation, trans.scale))
+		.collect();
+
+	for (mut transform, mut physics) in query.iter_mut() {
+		physics.grounded = false;
+
+		let mut motion = physics.velocity * delta;
+		for _ in 0..MAX_SWEEPS {
+			let hit = sweep_hitboxes(transform.translation, &physics.hitboxes, motion, &walls);
+			let (time_of_impact, normal) = match hit {
+				Some(hit) => hit,
+				None => {
+					transform.translation += motion;
+					break;
+				},
+			};
+
+			//move up to the surface then slide along it with what's left
+			transform.translation += motion * time_of_impact + normal * SKIN;
+			respond_to_hit(&mut physics, normal, friction);
+			let remaining = motion * (1.0 - time_of_impact);
+			motion = remaining - normal * remaining.dot(normal);
+		}
+
+		//anything still overlapping started inside a wall, push it out the short way
+		let hitboxes = physics.hitboxes.clone();
+		for &(wall_pos, wall_scale) in walls.iter() {
+			for &(trans_hitbox, scale_hitbox) in hitboxes.iter() {
+				let hitbox_pos = trans_hitbox + transform.translation;
+				if detect_collision((hitbox_pos, scale_hitbox), (wall_pos, wall_scale)) {
+					let (push, normal) = overlap_push((hitbox_pos, scale_hitbox), (wall_pos, wall_scale));
+					transform.translation += push;
+					respond_to_hit(&mut physics, normal, friction);
+					break;
+				}
+			}
+		}
+	}
+}
+
+fn respond_to_hit(physics: &mut Physics, normal: Vec3, friction: f32) {
+	if normal.y > 0.0 {
+		physics.grounded = true;
+		physics.velocity.y = f32::max(0.0, physics.velocity.y);
+		physics.velocity *= friction;
+	} else if normal.y < 0.0 {
+		physics.velocity.y = f32::min(0.0, physics.velocity.y);
+	} else if normal.x > 0.0 {
+		physics.velocity.x = f32::max(0.0, physics.velocity.x);
+	} else if normal.x < 0.0 {
+		physics.velocity.x = f32::min(0.0, physics.velocity.x);
+	} else if normal.z > 0.0 {
+		physics.velocity.z = f32::max(0.0, physics.velocity.z);
+	} else if normal.z < 0.0 {
+		physics.velocity.z = f32::min(0.0, physics.velocity.z);
+	}
+}
+
+//earliest hit of any of the hitboxes against any wall, as a fraction of motion and the wall normal
+pub fn sweep_hitboxes(position: Vec3, hitboxes: &[(Vec3, Vec3)], motion: Vec3, walls: &[(Vec3, Vec3)]) -> Option<(f32, Vec3)> {
+	let mut earliest: Option<(f32, Vec3)> = None;
+	for &(trans_hitbox, scale_hitbox) in hitboxes.iter() {
+		for &wall in walls.iter() {
+			if let Some((time, normal)) = sweep_box((position + trans_hitbox, scale_hitbox), motion, wall) {
+				if earliest.is_none_or(|(earliest_time, _)| time < earliest_time) {
+					earliest = Some((time, normal));
+				}
+			}
+		}
+	}
+	earliest
+}
+
+//boxes are centre and size, treats the wall grown by the hitbox as a ray cast from the hitbox centre
+pub fn sweep_box(hitbox: (Vec3, Vec3), motion: Vec3, wall: (Vec3, Vec3)) -> Option<(f32, Vec3)> {
+	let extent = (hitbox.1 + wall.1) / 2.0;
+	let min = wall.0 - extent;
+	let max = wall.0 + extent;
+
+	let mut entry = f32::MIN;
+	let mut exit = f32::MAX;
+	let mut normal = Vec3::ZERO;
+
+	for axis in 0..3 {
+		let start = hitbox.0[axis];
+		let change = motion[axis];
+
+		if change.abs() < f32::EPSILON {
+			//not moving on this axis so it has to already be between the faces
+			if start <= min[axis] || start >= max[axis] {
+				return None;
+			}
+			continue;
+		}
+
+		let to_min = (min[axis] - start) / change;
+		let to_max = (max[axis] - start) / change;
+		let (near, far) = if to_min < to_max { (to_min, to_max) } else { (to_max, to_min) };
+
+		if near > entry {
+			entry = near;
+			normal = Vec3::ZERO;
+			normal[axis] = -change.signum();
+		}
+		exit = f32::min(exit, far);
+	}
+
+	//slightly negative entry is a body resting right on the surface
+	let tolerance = SKIN / motion.length().max(SKIN);
+	if entry > exit || entry > 1.0 || entry < -tolerance || normal == Vec3::ZERO {
+		return None;
+	}
+	Some((entry.max(0.0), normal))
+}
+
+//smallest push that separates two overlapping boxes, with the normal it pushes along
+fn overlap_push(hitbox: (Vec3, Vec3), wall: (Vec3, Vec3)) -> (Vec3, Vec3) {
+	let mut best = (Vec3::ZERO, Vec3::ZERO);
+	let mut best_amount = f32::MAX;
+
+	for axis in 0..3 {
+		let hitbox_max = hitbox.0[axis] + hitbox.1[axis]/2.0;
+		let hitbox_min = hitbox.0[axis] - hitbox.1[axis]/2.0;
+		let wall_max = wall.0[axis] + wall.1[axis]/2.0;
+		let wall_min = wall.0[axis] - wall.1[axis]/2.0;
+
+		let positive_diff = wall_max - hitbox_min;
+		let negative_diff = hitbox_max - wall_min;
+
+		for (amount, sign) in [(positive_diff, 1.0), (negative_diff, -1.0)] {
+			if amount < best_amount {
+				best_amount = amount;
+				let mut normal = Vec3::ZERO;
+				normal[axis] = sign;
+				best = (normal * amount, normal);
+			}
+		}
+	}
+	best
+}
+
+pub fn detect_collision(hitbox: (Vec3, Vec3), wall: (Vec3, Vec3)) -> bool {
+	f32::abs(hitbox.0.x - wall.0.x) <= hitbox.1.x/2.0 + wall.1.x/2.0 &&
+	f32::abs(hitbox.0.y - wall.0.y) <= hitbox.1.y/2.0 + wall.1.y/2.0 &&
+	f32::abs(hitbox.0.z - wall.0.z) <= hitbox.1.z/2.0 + wall.1.z/2.0
+}
+
+//every collision box in the level, kept here so anything can cast rays without its own query
+//walls don't move so it's only rebuilt when new ones are spawned
+#[derive(Default)]
+pub struct Colliders {
+	pub boxes: Vec<(Entity, Vec3, Vec3)>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RayHit {
+	pub entity: Entity,
+	pub point: Vec3,
+	//zero when the ray started inside the box
+	pub normal: Vec3,
+	pub distance: f32,
+}
+
+impl Colliders {
+	//nearest box along the ray within max_distance, direction doesn't need to be normalised
+	pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+		let direction = direction.normalize_or_zero();
+		if direction == Vec3::ZERO {
+			return None;
+		}
+
+		let mut nearest: Option<RayHit> = None;
+		for &(entity, pos, scale) in self.boxes.iter() {
+			if let Some((distance, normal)) = ray_box(origin, direction, max_distance, (pos, scale)) {
+				if nearest.is_none_or(|hit| distance < hit.distance) {
+					nearest = Some(RayHit {
+						entity,
+						point: origin + direction * distance,
+						normal,
+						distance,
+					});
+				}
+			}
+		}
+		nearest
+	}
+
+	pub fn line_of_sight(&self, start: Vec3, end: Vec3) -> bool {
+		self.raycast(start, end - start, start.distance(end)).is_none()
+	}
+}
+
+//distance along a normalised ray to where it enters the box, and the face it goes in through
+pub fn ray_box(origin: Vec3, direction: Vec3, max_distance: f32, wall: (Vec3, Vec3)) -> Option<(f32, Vec3)> {
+	let min = wall.0 - wall.1/2.0;
+	let max = wall.0 + wall.1/2.0;
+
+	let mut entry = 0.0;
+	let mut exit = max_distance;
+	let mut normal = Vec3::ZERO;
+	for axis in 0..3 {
+		if direction[axis].abs() < f32::EPSILON {
+			if origin[axis] < min[axis] || origin[axis] > max[axis] {
+				return None;
+			}
+			continue;
+		}
+
+		let to_min = (min[axis] - origin[axis]) / direction[axis];
+		let to_max = (max[axis] - origin[axis]) / direction[axis];
+		let (near, far) = if to_min < to_max { (to_min, to_max) } else { (to_max, to_min) };
+		if near > entry {
+			entry = near;
+			normal = Vec3::ZERO;
+			normal[axis] = -direction[axis].signum();
+		}
+		exit = f32::min(exit, far);
+		if entry > exit {
+			return None;
+		}
+	}
+	Some((entry, normal))
+}
+
+//height of the highest top surface at or below the point
+pub fn ground_below(point: Vec3, walls: &[(Vec3, Vec3)]) -> Option<f32> {
+	walls.iter()
+		.filter(|(pos, scale)| {
+			f32::abs(point.x - pos.x) <= scale.x/2.0 && f32::abs(point.z - pos.z) <= scale.z/2.0
+		})
+		.map(|(pos, scale)| pos.y + scale.y/2.0)
+		.filter(|top| *top <= point.y)
+		.reduce(f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bevy::math::const_vec3;
+
+	//the same hitbox every body uses, hanging below the head
+	const HITBOX: (Vec3, Vec3) = (const_vec3!([0.0, -3.5, 0.0]), const_vec3!([0.4, 6.0, 0.4]));
+	//as thin as the level's platforms
+	const PLATFORM: (Vec3, Vec3) = (Vec3::ZERO, const_vec3!([6.0, 0.5, 6.0]));
+
+	fn world_with(wall: (Vec3, Vec3), head: Vec3, velocity: Vec3) -> (World, Entity) {
+		let mut world = World::new();
+		world.insert_resource(TickRate::default());
+		world.spawn()
+			.insert(Transform::from_translation(wall.0).with_scale(wall.1))
+			.insert(Collision);
+		let body = world.spawn()
+			.insert(Transform::from_translation(head))
+			.insert(Physics {
+				hitboxes: vec![HITBOX],
+				velocity,
+				grounded: false,
+			})
+			.id();
+		(world, body)
+	}
+
+	fn ticker(gravity: bool) -> SystemStage {
+		let mut stage = SystemStage::single_threaded()
+			.with_system(physics_system.label("physics"));
+		if gravity {
+			stage.add_system(gravity_system.before("physics"));
+		}
+		stage
+	}
+
+	#[test]
+	fn fast_fall_lands_on_platform() {
+		//far more than the platform's thickness every tick
+		let (mut world, body) = world_with(PLATFORM, Vec3::new(0.0, 30.0, 0.0), Vec3::new(0.0, -200.0, 0.0));
+		let mut stage = ticker(true);
+		let top = PLATFORM.0.y + PLATFORM.1.y / 2.0;
+
+		for _ in 0..60 {
+			stage.run(&mut world);
+			let feet = world.get::<Transform>(body).unwrap().translation.y + HITBOX.0.y - HITBOX.1.y / 2.0;
+			assert!(feet >= top - SKIN, "fell through to {}", feet);
+		}
+
+		let feet = world.get::<Transform>(body).unwrap().translation.y + HITBOX.0.y - HITBOX.1.y / 2.0;
+		assert!((feet - top).abs() < 0.01, "resting at {} instead of {}", feet, top);
+		assert!(world.get::<Physics>(body).unwrap().grounded);
+	}
+
+	#[test]
+	fn fast_side_hit_stops_at_face() {
+		//stood on its end so it's a wall only as thick as a platform
+		let wall = (Vec3::ZERO, Vec3::new(0.5, 20.0, 6.0));
+		let (mut world, body) = world_with(wall, Vec3::new(-10.0, 5.0, 0.0), Vec3::new(300.0, 0.0, 0.0));
+		let mut stage = ticker(false);
+		let face = wall.0.x - wall.1.x / 2.0 - HITBOX.1.x / 2.0;
+
+		for _ in 0..30 {
+			stage.run(&mut world);
+			let x = world.get::<Transform>(body).unwrap().translation.x;
+			assert!(x <= face, "went through to {}", x);
+		}
+
+		let x = world.get::<Transform>(body).unwrap().translation.x;
+		assert!((x - face).abs() < 0.01, "stopped at {} instead of {}", x, face);
+		assert_eq!(world.get::<Physics>(body).unwrap().velocity.x, 0.0);
+	}
+}
+
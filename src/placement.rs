@@ -0,0 +1,152 @@
+use crate::ai::FEET;
+use crate::physics::detect_collision;
+use crate::rng::GameRng;
+use bevy::prelude::*;
+
+//how far enemies appear from the player and from each other
+const PLAYER_SPACE: f32 = 20.0;
+const ENEMY_SPACE: f32 = 5.0;
+
+const ATTEMPTS: usize = 30;
+//kept clear of the edge so a body isn't left hanging off it
+const EDGE_MARGIN: f32 = 0.5;
+//dropped in just above the floor so it isn't touching it
+const LIFT: f32 = 0.01;
+
+//somewhere a body can stand on top of a wall, given as where its head goes
+//avoid is points with how close they can be, near keeps it within a distance of a point
+pub fn place_on_platform(
+	walls: &[(Vec3, Vec3)],
+	avoid: &[(Vec3, f32)],
+	near: Option<(Vec3, f32)>,
+	rng: &mut GameRng,
+) -> Option<Vec3> {
+	let candidates: Vec<(Vec3, Vec3)> = walls.iter()
+		.copied()
+		.filter(|(_, scale)| scale.x > EDGE_MARGIN * 2.0 && scale.z > EDGE_MARGIN * 2.0)
+		.filter(|&(pos, scale)| match near {
+			Some((centre, distance)) => top_distance(pos, scale, centre) <= distance,
+			None => true,
+		})
+		.collect();
+	if candidates.is_empty() {
+		return None;
+	}
+
+	//bigger tops get picked more so the spread is even over the floor
+	let total_area: f32 = candidates.iter().map(|(_, scale)| scale.x * scale.z).sum();
+
+	for _ in 0..ATTEMPTS {
+		let mut choice = rng.f32() * total_area;
+		let &(pos, scale) = candidates.iter()
+			.find(|(_, scale)| {
+				choice -= scale.x * scale.z;
+				choice < 0.0
+			})
+			.unwrap_or(&candidates[candidates.len() - 1]);
+
+		let x = pos.x + rng.centred() * (scale.x - EDGE_MARGIN * 2.0);
+		let z = pos.z + rng.centred() * (scale.z - EDGE_MARGIN * 2.0);
+		let top = pos.y + scale.y/2.0;
+		let head = Vec3::new(x, top - FEET + LIFT, z);
+
+		if let Some((centre, distance)) = near {
+			if Vec2::new(head.x - centre.x, head.z - centre.z).length() > distance {
+				continue;
+			}
+		}
+		if avoid.iter().any(|&(point, space)| head.distance(point) < space) {
+			continue;
+		}
+		//nothing in the way of standing up, same hitbox every body uses
+		let body = (head + Vec3::new(0.0, -3.5, 0.0), Vec3::new(0.4, 6.0, 0.4));
+		if walls.iter().any(|&wall| detect_collision(body, wall)) {
+			continue;
+		}
+		return Some(head);
+	}
+	None
+}
+
+//horizontal distance from a point to the top face of a wall
+fn top_distance(pos: Vec3, scale: Vec3, point: Vec3) -> f32 {
+	let half = Vec2::new(scale.x, scale.z) / 2.0;
+	let centre = Vec2::new(pos.x, pos.z);
+	let point = Vec2::new(point.x, point.z);
+	point.clamp(centre - half, centre + half).distance(point)
+}
+
+//what place_on_platform should keep away from, the player by a lot and other enemies by a bit
+pub fn spacing(players: impl IntoIterator<Item = Vec3>, enemies: impl IntoIterator<Item = Vec3>) -> Vec<(Vec3, f32)> {
+	players.into_iter()
+		.map(|point| (point, PLAYER_SPACE))
+		.chain(enemies.into_iter().map(|point| (point, ENEMY_SPACE)))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bevy::math::const_vec3;
+
+	//a big floor, a smaller one off to the side and a raised step
+	const WALLS: [(Vec3, Vec3); 3] = [
+		(const_vec3!([0.0, -0.25, 0.0]), const_vec3!([40.0, 0.5, 40.0])),
+		(const_vec3!([30.0, -0.25, -20.0]), const_vec3!([11.0, 0.5, 11.0])),
+		(const_vec3!([5.0, 1.25, -12.0]), const_vec3!([4.0, 0.5, 4.0])),
+	];
+	const PLAYER: Vec3 = const_vec3!([0.0, 6.51, 0.0]);
+
+	//the wall a spawned head is stood on, if any
+	fn standing_on(head: Vec3) -> Option<(Vec3, Vec3)> {
+		let feet = head.y + FEET - LIFT;
+		WALLS.iter()
+			.copied()
+			.find(|(pos, scale)| {
+				(feet - (pos.y + scale.y/2.0)).abs() < 0.001
+					&& (head.x - pos.x).abs() <= scale.x/2.0 - EDGE_MARGIN
+					&& (head.z - pos.z).abs() <= scale.z/2.0 - EDGE_MARGIN
+			})
+	}
+
+	#[test]
+	fn spawns_land_over_a_platform() {
+		let mut rng = GameRng::new(3);
+		for _ in 0..500 {
+			let head = place_on_platform(&WALLS, &[], None, &mut rng).unwrap();
+			assert!(standing_on(head).is_some(), "{} isn't stood on anything", head);
+		}
+	}
+
+	#[test]
+	fn spawns_near_a_point_stay_near_it() {
+		let mut rng = GameRng::new(4);
+		let centre = WALLS[1].0;
+		for _ in 0..500 {
+			let head = place_on_platform(&WALLS, &[], Some((centre, 3.0)), &mut rng).unwrap();
+			assert!(standing_on(head).is_some(), "{} isn't stood on anything", head);
+			assert!(Vec2::new(head.x - centre.x, head.z - centre.z).length() <= 3.0);
+		}
+	}
+
+	#[test]
+	fn spawns_keep_their_spacing() {
+		let mut rng = GameRng::new(5);
+		let mut enemies: Vec<Vec3> = Vec::new();
+		//the way the waves fill up, each one keeping clear of all the ones before
+		for _ in 0..20 {
+			let avoid = spacing([PLAYER], enemies.iter().copied());
+			let head = match place_on_platform(&WALLS, &avoid, None, &mut rng) {
+				Some(head) => head,
+				None => continue,
+			};
+			assert!(standing_on(head).is_some(), "{} isn't stood on anything", head);
+			assert!(head.distance(PLAYER) >= PLAYER_SPACE, "{} is too close to the player", head);
+			for other in enemies.iter() {
+				assert!(head.distance(*other) >= ENEMY_SPACE, "{} is too close to {}", head, other);
+			}
+			enemies.push(head);
+		}
+		assert!(enemies.len() > 10, "only found room for {}", enemies.len());
+	}
+}
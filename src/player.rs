@@ -0,0 +1,385 @@
+use crate::{Player, Head, Sticky, PlayerHead};
+use crate::spherical::Spherical;
+use crate::map::{Level, CurrentLevel};
+use crate::anim::{AnimPos, AnimInfo,
+    STICK_SIZE, MAJOR_HEIGHT, MINOR_HEIGHT,
+    LEFT_ARM, RIGHT_ARM, LEFT_LEG, RIGHT_LEG,
+};
+use crate::anim_graph::PLAYER_GRAPH;
+use crate::physics::{Physics, Colliders};
+use crate::shot::{PlayerMaterial, ShotMesh, Shot, ShotPhysics, create_shot};
+use crate::weapon::{Weapon, LoadedWeapons, WeaponLabel};
+use crate::state::{AppState, LOSE_Y};
+use crate::enemy::Health;
+use crate::tick::{Interpolated, TickLabel, TickRate, FixedUpdateStage, playing_tick_set};
+use crate::controls::PlayerInput;
+use std::f32::consts::PI;
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use std::marker::PhantomData;
+
+//push a second while a direction's held, the ground's friction keeps the top speed well under it
+const VELOCITY: f32 = 270.0;
+//share of the push the player still gets in the air
+const AIR_CONTROL: f32 = 0.03;
+const JUMP_HEIGHT: f32 = 4.0;
+
+
+//keeps the camera this far in front of whatever it would have gone behind
+const CAMERA_PADDING: f32 = 0.3;
+const CAMERA_MIN_DISTANCE: f32 = 1.0;
+
+//seconds after a hit where more shots do nothing
+pub const HIT_INVULNERABILITY: f32 = 1.0;
+//health lost for falling off when that doesn't end the game
+const FALL_DAMAGE: u16 = 1;
+
+pub struct PlayerPlugin;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct CameraLabel;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct FallLabel;
+
+#[derive(Component)]
+pub struct Invulnerable {
+    pub remaining: f32,
+}
+
+//how far the camera wants to sit from the head, it gets pulled in when a platform's in the way
+#[derive(Component)]
+pub struct CameraArm {
+    pub length: f32,
+}
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+                SystemSet::on_enter(AppState::Playing)
+                    .with_system(create_player)
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                playing_tick_set()
+                    .with_system(move_camera.label(CameraLabel).label(TickLabel::Input).after(TickLabel::Sample))
+                    .with_system(move_player.label(TickLabel::Input).after(CameraLabel))
+                    .with_system(player_shoot_system.label(TickLabel::Input).after(CameraLabel).after(WeaponLabel))
+                    .with_system(invulnerability_system.after(TickLabel::Sample).before(TickLabel::Collision))
+                    .with_system(respawn_fallen_player_system.label(FallLabel).after(TickLabel::Physics).before(TickLabel::Collision))
+            );
+    }
+}
+
+pub fn create_player(
+    mut commands: Commands, 
+    mut meshes: ResMut<Assets<Mesh>>, 
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    levels: Res<Assets<Level>>,
+    current_level: Res<CurrentLevel>,
+) {
+    let level = levels.get(&current_level.handle).unwrap();
+
+    let sphere_handle = meshes.add(Mesh::from(shape::UVSphere::default()));
+
+    let material_handle = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.0, 0.0, 0.0),
+        unlit: true,
+        ..StandardMaterial::default()
+    });
+
+    let main_line_handle = meshes.add(Mesh::from(shape::Box::new(STICK_SIZE, MAJOR_HEIGHT, STICK_SIZE)));
+    let minor_line_handle = meshes.add(Mesh::from(shape::Box::new(STICK_SIZE, MINOR_HEIGHT, STICK_SIZE)));
+
+    let mut camera_bundle = PerspectiveCameraBundle::new_3d();
+    camera_bundle.transform = Transform::from_xyz(0.0, 8.0, -9.0)
+        .with_rotation(Quat::from_euler(EulerRot::XYZ, 3.9, 0.0, PI));
+
+    //first, sphere head
+    commands.spawn_bundle(PbrBundle {
+        mesh: sphere_handle,
+        material: material_handle.clone(),
+        transform: Transform::from_translation(level.player_spawn),
+        ..PbrBundle::default()
+    })
+    .insert(Player)
+    .insert(Sticky::Player)
+    .insert(Head)
+    .insert(Weapon::default())
+    .insert(Health {
+        amount: level.player_health,
+        max: level.player_health,
+    })
+    .insert(Invulnerable {
+        remaining: 0.0,
+    })
+    .insert(Interpolated::new(level.player_spawn))
+    .insert(Physics {
+        hitboxes: vec![
+            (Vec3::new(0.0, -3.5, 0.0), Vec3::new(0.4, 6.0, 0.4)),
+        ],
+        velocity: Vec3::new(0.0, 0.0, 0.0),
+        grounded: false,
+    })
+    .insert(AnimInfo::new(PLAYER_GRAPH))
+    .with_children(|parent| {
+
+        let arm_length = camera_bundle.transform.translation.length();
+        //turned and moved in the tick, so it's smoothed the same way the head is
+        let camera_interpolated = Interpolated::from_transform(&camera_bundle.transform);
+        parent.spawn_bundle(camera_bundle)
+            .insert(CameraArm {
+                length: arm_length,
+            })
+            .insert(camera_interpolated);
+
+        parent.spawn_bundle(PbrBundle {
+            mesh: main_line_handle,
+            material: material_handle.clone(),
+            transform: Transform::from_xyz(0.0, - MAJOR_HEIGHT/2.0 - 1.0, 0.0),
+            ..PbrBundle::default()
+        })
+        .insert(Player)
+        .insert(Sticky::Player)
+        .with_children(|parent| { 
+            parent.spawn_bundle(PbrBundle {
+                mesh: minor_line_handle.clone(),
+                material: material_handle.clone(),
+                ..PbrBundle::default()
+            })
+            .insert(Player)
+            .insert(Sticky::Player)
+            .insert(AnimPos::default())
+            .insert(LEFT_ARM);
+
+            parent.spawn_bundle(PbrBundle {
+                mesh: minor_line_handle.clone(),
+                material: material_handle.clone(),
+                ..PbrBundle::default()
+            })
+            .insert(Player)
+            .insert(Sticky::Player)
+            .insert(AnimPos::default())
+            .insert(RIGHT_ARM);
+            parent.spawn_bundle(PbrBundle {
+                mesh: minor_line_handle.clone(),
+                material: material_handle.clone(),
+                ..PbrBundle::default()
+            })
+            .insert(Player)
+            .insert(Sticky::Player)
+            .insert(AnimPos::default())
+            .insert(LEFT_LEG);
+            
+            parent.spawn_bundle(PbrBundle {
+                mesh: minor_line_handle,
+                material: material_handle.clone(),
+                ..PbrBundle::default()
+            })
+            .insert(Player)
+            .insert(Sticky::Player)
+            .insert(AnimPos::default())
+            .insert(RIGHT_LEG);
+        });
+    });
+
+    commands.insert_resource(PlayerMaterial {
+        player_mat: material_handle,
+    });
+}
+
+pub fn move_player(
+    input: Res<PlayerInput>,
+    mut query: Query<(&mut Physics, &Transform), PlayerHead>,
+    tick_rate: Res<TickRate>,
+) {
+    let (mut physics, trans) = query.get_single_mut().unwrap();
+
+    let velocity = if physics.grounded {
+        VELOCITY * tick_rate.step()
+    } else {
+        VELOCITY * AIR_CONTROL * tick_rate.step()
+    };
+
+    if input.forward {
+        physics.velocity += trans.rotation * Vec3::new(0.0, 0.0, velocity);
+    }
+    if input.left {
+        physics.velocity += trans.rotation * Vec3::new(velocity, 0.0, 0.0);
+    }
+    if input.right {
+        physics.velocity += trans.rotation * Vec3::new(-velocity, 0.0, 0.0);
+    }
+    if input.back {
+        physics.velocity += trans.rotation * Vec3::new(0.0, 0.0, -velocity);
+    }
+    if input.jump && physics.grounded {
+        physics.velocity += trans.rotation * Vec3::new(0.0, JUMP_HEIGHT, 0.0);
+    }
+}
+
+//what the player's shots are drawn with
+#[derive(SystemParam)]
+pub struct PlayerShotAssets<'w, 's> {
+    mesh: Res<'w, ShotMesh>,
+    material: Res<'w, PlayerMaterial>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+pub fn player_shoot_system(
+    mut commands: Commands,
+    shot_assets: PlayerShotAssets,
+    input: Res<PlayerInput>,
+    mut player_query: Query<(Entity, &Transform, &mut Weapon), PlayerHead>,
+    camera_query: Query<&Transform, (With<Camera>, Without<Head>)>,
+    weapons: LoadedWeapons,
+    tick_rate: Res<TickRate>,
+) {
+    let (player, player_trans, mut weapon) = player_query.get_single_mut().unwrap();
+    //picked before a reload took it away, it gets clamped next tick
+    let definition = match weapons.definitions().weapons.get(weapon.selected) {
+        Some(definition) => definition,
+        None => return,
+    };
+
+    weapon.cooldown -= tick_rate.step();
+    let power = match weapon.pull_trigger(definition, &input, tick_rate.step()) {
+        Some(power) => power,
+        None => return,
+    };
+
+    let camera_trans = camera_query.get_single().unwrap();
+    //camera is a child of the head, global transforms lag behind within a tick
+    let aim = player_trans.rotation * camera_trans.rotation;
+    let projectile = &definition.projectile;
+    for angle in definition.pellet_angles() {
+        let shot = create_shot(
+            &mut commands,
+            shot_assets.mesh.get(projectile.shape),
+            shot_assets.material.player_mat.clone(),
+            player_trans.translation,
+            Shot {
+                size: projectile.size * power,
+                damage: (projectile.damage as f32 * power).round() as u16,
+                owner: player,
+            },
+            ShotPhysics {
+                velocity: aim * Quat::from_rotation_y(angle) * Vec3::new(0.0, 0.0, -projectile.speed),
+                gravity: projectile.gravity,
+                response: projectile.response,
+            },
+            Sticky::Player,
+        );
+        if let Some(explosion) = definition.explosion {
+            commands.entity(shot).insert(explosion);
+        }
+    }
+    weapon.cooldown = definition.cooldown;
+}
+
+pub fn invulnerability_system(
+    mut query: Query<&mut Invulnerable>,
+    tick_rate: Res<TickRate>,
+) {
+    for mut invulnerable in query.iter_mut() {
+        invulnerable.remaining = f32::max(invulnerable.remaining - tick_rate.step(), 0.0);
+    }
+}
+
+//only when the level doesn't end the game for falling, otherwise they're left to lose
+pub fn respawn_fallen_player_system(
+    mut query: Query<(&mut Transform, &mut Interpolated, &mut Physics, &mut Health), PlayerHead>,
+    levels: Res<Assets<Level>>,
+    current_level: Res<CurrentLevel>,
+) {
+    let level = levels.get(&current_level.handle).unwrap();
+    if level.lose.uses_falling() {
+        return;
+    }
+
+    let (mut trans, mut interpolated, mut physics, mut health) = query.get_single_mut().unwrap();
+    if trans.translation.y < LOSE_Y {
+        interpolated.snap(&mut trans, level.player_spawn);
+        physics.velocity = Vec3::ZERO;
+        health.amount = health.amount.saturating_sub(FALL_DAMAGE);
+    }
+}
+
+pub fn move_camera(
+    input: Res<PlayerInput>,
+    colliders: Res<Colliders>,
+    mut camera_query: Query<(&mut Transform, &CameraArm), With<Camera>>,
+    mut player_query: Query<&mut Transform, (PlayerHead, Without<Camera>)>,
+) {
+    let (mut camera_transform, arm) = camera_query.get_single_mut().unwrap();
+
+    let mut player_trans = player_query.get_single_mut().unwrap();
+
+    let delta_x = input.look.x;
+    let delta_y = input.look.y;
+    let yaw = Quat::from_rotation_y(-delta_x);
+
+    let (mut x, y, z) = camera_transform.rotation.to_euler(EulerRot::XYZ);
+    x = add_clamp_around(x, -delta_y, PI/2.0, 0.1);
+    camera_transform.rotation = Quat::from_euler(EulerRot::XYZ, x, y, z);
+
+    player_trans.rotation = yaw * player_trans.rotation; // rotate around global y axis
+
+
+    let mut cam_sphere = Spherical::from_vec3(camera_transform.translation);
+    cam_sphere.phi = f32::clamp(cam_sphere.phi + delta_y, 0.1, PI - 0.1);
+    cam_sphere.radius = arm.length;
+
+    //pull the camera in front of anything between it and the head
+    let wanted = player_trans.rotation * cam_sphere.to_vec3();
+    if let Some(hit) = colliders.raycast(player_trans.translation, wanted, arm.length) {
+        cam_sphere.radius = f32::max(hit.distance - CAMERA_PADDING, CAMERA_MIN_DISTANCE);
+    }
+    camera_transform.translation = cam_sphere.to_vec3();
+
+}
+
+//awful
+fn add_clamp_around(val: f32, change: f32, pos: f32, clamp_val: f32) -> f32 {
+    let new_val = val + change;
+    if val < -pos {
+        if new_val > -pos - clamp_val {
+            return -pos - clamp_val;
+        }
+    } else if val > pos{
+        if new_val < pos + clamp_val {
+            return pos + clamp_val;
+        }
+    } else {
+        if new_val > pos - clamp_val {
+            return pos - clamp_val;
+        }
+        if new_val < -pos + clamp_val {
+            return -pos + clamp_val;
+        }
+    }
+    return new_val;
+}
+
+pub fn cursor_grab_system(
+    mut windows: ResMut<Windows>,
+    btn: Res<Input<MouseButton>>,
+    key: Res<Input<KeyCode>>,
+) {
+    let window = windows.get_primary_mut().unwrap();
+
+    if btn.just_pressed(MouseButton::Left) {
+        window.set_cursor_lock_mode(true);
+        window.set_cursor_visibility(false);
+    }
+
+    if key.just_pressed(KeyCode::Escape) {
+        window.set_cursor_lock_mode(false);
+        window.set_cursor_visibility(true);
+    }
+
+    if window.cursor_locked() {
+        window.set_cursor_position(Vec2::new(window.physical_width() as f32, window.physical_height() as f32));
+    }
+}
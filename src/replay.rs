@@ -0,0 +1,317 @@
+use crate::{Player, Enemy, Head};
+use crate::controls::PlayerInput;
+use crate::state::AppState;
+use crate::tick::{TickCount, TickLabel, FixedUpdateStage, playing_tick_set};
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+//a replay is the seed, tick rate and level then one record per tick of input,
+//with a checksum of where everyone is every CHECKSUM_INTERVAL ticks
+//version 2 added holding shoot and picking weapons
+const MAGIC: &[u8; 4] = b"BGJR";
+const VERSION: u8 = 2;
+pub const CHECKSUM_INTERVAL: u64 = 30;
+
+const INPUT_RECORD: u8 = 0;
+const CHECKSUM_RECORD: u8 = 1;
+
+const FORWARD_BIT: u8 = 1;
+const BACK_BIT: u8 = 1 << 1;
+const LEFT_BIT: u8 = 1 << 2;
+const RIGHT_BIT: u8 = 1 << 3;
+const JUMP_BIT: u8 = 1 << 4;
+const SHOOT_BIT: u8 = 1 << 5;
+const SHOOT_HELD_BIT: u8 = 1 << 6;
+
+//written in place of a weapon slot when none was picked
+const NO_SELECT: u8 = u8::MAX;
+
+//everything whose position goes into a checksum
+type Checksummed = (With<Head>, Or<(With<Player>, With<Enemy>)>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct PlaybackLabel;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+	fn build(&self, app: &mut App) {
+		let mut tick_set = playing_tick_set();
+		if app.world.contains_resource::<Replay>() {
+			app.init_resource::<ReplayStatus>();
+			tick_set = tick_set
+				.with_system(replay_input_system.label(PlaybackLabel).after(TickLabel::Sample).before(TickLabel::Input))
+				.with_system(verify_checksum_system.after(TickLabel::Rules));
+		} else if app.world.contains_resource::<ReplayRecorder>() {
+			tick_set = tick_set
+				.with_system(record_input_system.after(TickLabel::Sample).before(TickLabel::Input))
+				.with_system(record_checksum_system.after(TickLabel::Rules));
+			app.add_system_set(
+				SystemSet::on_exit(AppState::Playing)
+					.with_system(finish_recording_system)
+			);
+		}
+		app.add_system_set_to_stage(FixedUpdateStage, tick_set);
+	}
+}
+
+pub struct ReplayRecorder {
+	writer: BufWriter<File>,
+	finished: bool,
+}
+
+impl ReplayRecorder {
+	pub fn create(path: &str, seed: u64, tick_rate: f64, level: &str) -> io::Result<ReplayRecorder> {
+		let level_len = u16::try_from(level.len())
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "level path too long"))?;
+		let mut writer = BufWriter::new(File::create(path)?);
+		writer.write_all(MAGIC)?;
+		writer.write_all(&[VERSION])?;
+		writer.write_all(&seed.to_le_bytes())?;
+		writer.write_all(&tick_rate.to_le_bytes())?;
+		writer.write_all(&level_len.to_le_bytes())?;
+		writer.write_all(level.as_bytes())?;
+		Ok(ReplayRecorder {
+			writer,
+			finished: false,
+		})
+	}
+
+	fn write_input(&mut self, input: &PlayerInput) -> io::Result<()> {
+		let mut flags = 0;
+		for (pressed, bit) in [
+			(input.forward, FORWARD_BIT),
+			(input.back, BACK_BIT),
+			(input.left, LEFT_BIT),
+			(input.right, RIGHT_BIT),
+			(input.jump, JUMP_BIT),
+			(input.shoot, SHOOT_BIT),
+			(input.shoot_held, SHOOT_HELD_BIT),
+		] {
+			if pressed {
+				flags |= bit;
+			}
+		}
+		let select = input.select.unwrap_or(NO_SELECT);
+		self.writer.write_all(&[INPUT_RECORD, flags, select, input.cycle as u8])?;
+		self.writer.write_all(&input.look.x.to_le_bytes())?;
+		self.writer.write_all(&input.look.y.to_le_bytes())
+	}
+
+	fn write_checksum(&mut self, tick: u64, checksum: u64) -> io::Result<()> {
+		self.writer.write_all(&[CHECKSUM_RECORD])?;
+		self.writer.write_all(&tick.to_le_bytes())?;
+		self.writer.write_all(&checksum.to_le_bytes())?;
+		self.writer.flush()
+	}
+}
+
+pub struct Replay {
+	pub seed: u64,
+	pub tick_rate: f64,
+	//path of the level it was played on, under assets
+	pub level: String,
+	inputs: Vec<PlayerInput>,
+	checksums: HashMap<u64, u64>,
+	next: usize,
+}
+
+impl Replay {
+	pub fn load(path: &str) -> Result<Replay, String> {
+		let bytes = std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+		Replay::from_bytes(&bytes).map_err(|err| format!("{}: {}", path, err))
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<Replay, String> {
+		let mut reader = ByteReader { bytes, pos: 0 };
+		if reader.take(4)? != MAGIC {
+			return Err("not a replay file".to_string());
+		}
+		let version = reader.take(1)?[0];
+		if version != VERSION {
+			return Err(format!("replay version {} but this build reads {}", version, VERSION));
+		}
+
+		let mut replay = Replay {
+			seed: reader.u64()?,
+			tick_rate: f64::from_bits(reader.u64()?),
+			level: reader.string()?,
+			inputs: Vec::new(),
+			checksums: HashMap::new(),
+			next: 0,
+		};
+
+		while !reader.is_empty() {
+			match reader.take(1)?[0] {
+				INPUT_RECORD => {
+					let flags = reader.take(1)?[0];
+					let select = reader.take(1)?[0];
+					let cycle = reader.take(1)?[0] as i8;
+					replay.inputs.push(PlayerInput {
+						forward: flags & FORWARD_BIT != 0,
+						back: flags & BACK_BIT != 0,
+						left: flags & LEFT_BIT != 0,
+						right: flags & RIGHT_BIT != 0,
+						jump: flags & JUMP_BIT != 0,
+						shoot: flags & SHOOT_BIT != 0,
+						shoot_held: flags & SHOOT_HELD_BIT != 0,
+						select: if select == NO_SELECT { None } else { Some(select) },
+						cycle,
+						look: Vec2::new(reader.f32()?, reader.f32()?),
+					});
+				},
+				CHECKSUM_RECORD => {
+					let tick = reader.u64()?;
+					let checksum = reader.u64()?;
+					replay.checksums.insert(tick, checksum);
+				},
+				record => return Err(format!("unknown record {} at byte {}", record, reader.pos - 1)),
+			}
+		}
+
+		Ok(replay)
+	}
+}
+
+struct ByteReader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+	fn is_empty(&self) -> bool {
+		self.pos >= self.bytes.len()
+	}
+
+	fn take(&mut self, amount: usize) -> Result<&'a [u8], String> {
+		let slice = self.bytes.get(self.pos..self.pos + amount)
+			.ok_or_else(|| format!("replay cut off at byte {}", self.pos))?;
+		self.pos += amount;
+		Ok(slice)
+	}
+
+	fn u64(&mut self) -> Result<u64, String> {
+		let mut buf = [0; 8];
+		buf.copy_from_slice(self.take(8)?);
+		Ok(u64::from_le_bytes(buf))
+	}
+
+	fn string(&mut self) -> Result<String, String> {
+		let mut buf = [0; 2];
+		buf.copy_from_slice(self.take(2)?);
+		let len = u16::from_le_bytes(buf) as usize;
+		String::from_utf8(self.take(len)?.to_vec())
+			.map_err(|_| format!("bad text before byte {}", self.pos))
+	}
+
+	fn f32(&mut self) -> Result<f32, String> {
+		let mut buf = [0; 4];
+		buf.copy_from_slice(self.take(4)?);
+		Ok(f32::from_le_bytes(buf))
+	}
+}
+
+//how playback is going, tests can check this once a replay has run
+#[derive(Debug, Default)]
+pub struct ReplayStatus {
+	pub diverged_at: Option<u64>,
+	pub checksums_matched: usize,
+	pub finished: bool,
+}
+
+pub fn replay_input_system(
+	mut replay: ResMut<Replay>,
+	mut status: ResMut<ReplayStatus>,
+	mut input: ResMut<PlayerInput>,
+) {
+	match replay.inputs.get(replay.next).copied() {
+		Some(recorded) => {
+			*input = recorded;
+			replay.next += 1;
+		},
+		None => {
+			*input = PlayerInput::default();
+			if !status.finished {
+				info!("replay finished after {} ticks", replay.inputs.len());
+				status.finished = true;
+			}
+		},
+	}
+}
+
+pub fn verify_checksum_system(
+	replay: Res<Replay>,
+	mut status: ResMut<ReplayStatus>,
+	tick_count: Res<TickCount>,
+	query: Query<&Transform, Checksummed>,
+) {
+	let expected = match replay.checksums.get(&tick_count.count) {
+		Some(&expected) => expected,
+		None => return,
+	};
+
+	if checksum(query.iter()) == expected {
+		status.checksums_matched += 1;
+	} else if status.diverged_at.is_none() {
+		error!("replay diverged at tick {}", tick_count.count);
+		status.diverged_at = Some(tick_count.count);
+	}
+}
+
+pub fn record_input_system(
+	mut recorder: ResMut<ReplayRecorder>,
+	input: Res<PlayerInput>,
+) {
+	if recorder.finished {
+		return;
+	}
+	if let Err(err) = recorder.write_input(&input) {
+		error!("stopped recording replay: {}", err);
+		recorder.finished = true;
+	}
+}
+
+pub fn record_checksum_system(
+	mut recorder: ResMut<ReplayRecorder>,
+	tick_count: Res<TickCount>,
+	query: Query<&Transform, Checksummed>,
+) {
+	if recorder.finished || !tick_count.count.is_multiple_of(CHECKSUM_INTERVAL) {
+		return;
+	}
+	if let Err(err) = recorder.write_checksum(tick_count.count, checksum(query.iter())) {
+		error!("stopped recording replay: {}", err);
+		recorder.finished = true;
+	}
+}
+
+//a replay only covers one match, a restart starts a different game
+pub fn finish_recording_system(
+	mut recorder: ResMut<ReplayRecorder>,
+) {
+	if !recorder.finished {
+		if let Err(err) = recorder.writer.flush() {
+			error!("could not finish replay: {}", err);
+		}
+		recorder.finished = true;
+	}
+}
+
+//fnv-1a over the exact bits so any drift at all shows up,
+//each entity hashed on its own and summed as queries don't promise an order
+fn checksum<'a>(transforms: impl Iterator<Item = &'a Transform>) -> u64 {
+	transforms.fold(0, |sum, transform| {
+		let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+		let values = transform.translation.to_array().into_iter()
+			.chain(Vec4::from(transform.rotation).to_array());
+		for value in values {
+			for byte in value.to_bits().to_le_bytes() {
+				hash ^= u64::from(byte);
+				hash = hash.wrapping_mul(0x0100_0000_01b3);
+			}
+		}
+		sum.wrapping_add(hash)
+	})
+}
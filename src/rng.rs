@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+
+//every random choice in the game comes from here so a seed replays the same match
+//wyrand, same as fastrand uses, but owned so the resource can be shared between systems
+pub struct GameRng {
+	seed: u64,
+	state: u64,
+}
+
+impl GameRng {
+	pub fn new(seed: u64) -> GameRng {
+		GameRng {
+			seed,
+			state: seed,
+		}
+	}
+
+	pub fn from_entropy() -> GameRng {
+		GameRng::new(fastrand::u64(..))
+	}
+
+	pub fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	pub fn u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0xA076_1D64_78BD_642F);
+		let t = u128::from(self.state) * u128::from(self.state ^ 0xE703_7ED1_A0B4_28DB);
+		(t as u64) ^ (t >> 64) as u64
+	}
+
+	//between 0 and 1
+	pub fn f32(&mut self) -> f32 {
+		(self.u64() >> 40) as f32 / (1u64 << 24) as f32
+	}
+
+	//between -0.5 and 0.5, what most of the jitter wants
+	pub fn centred(&mut self) -> f32 {
+		self.f32() - 0.5
+	}
+
+	pub fn usize(&mut self, max: usize) -> usize {
+		(self.u64() % max as u64) as usize
+	}
+
+	pub fn shuffle<T>(&mut self, slice: &mut [T]) {
+		for i in (1..slice.len()).rev() {
+			let j = self.usize(i + 1);
+			slice.swap(i, j);
+		}
+	}
+}
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.get_resource_or_insert_with(GameRng::from_entropy);
+		app.add_startup_system(print_seed_system);
+	}
+}
+
+fn print_seed_system(
+	rng: Res<GameRng>,
+) {
+	info!("rng seed is {}, pass --seed {} to play this match again", rng.seed(), rng.seed());
+}
@@ -1,6 +1,19 @@
-use crate::{Sticky, Physics, Head, Player, Enemy, AppState, detect_collision};
-use crate::enemy::{EnemyMaterials, Health, EnemyColour, EnemyNum};
+use crate::{Sticky, Head, PlayerHead, EnemyHead};
+use crate::map::{Collision, Level, CurrentLevel};
+use crate::placement::{place_on_platform, spacing};
+use crate::physics::{Physics, Colliders, RayHit, detect_collision};
+use crate::enemy::Health;
+use crate::player::{Invulnerable, HIT_INVULNERABILITY};
+use crate::state::AppState;
+use crate::wave::WaveDirector;
+use crate::archetype::Archetype;
+use crate::aim::{ballistic_velocity, apply_spread};
+use crate::ai::AiLabel;
+use crate::rng::GameRng;
+use crate::tick::{TickRate, TickLabel, Interpolated, FixedUpdateStage, playing_tick_set};
+use serde::Deserialize;
 use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
 
 const DELETE_SHOT: f32 = -20.0;
 const SHOT_SIZE: f32 = 0.3;
@@ -8,34 +21,186 @@ const SHOT_SIZE: f32 = 0.3;
 //permissive to make it a little easier
 const STICKY_SIZE: f32 = 0.8;
 
+//a bounce slower than this off the surface just stops it
+const MIN_BOUNCE_SPEED: f32 = 1.0;
+
+pub struct ShotPlugin;
+
+//what happens when a shot hits someone, detection sends the events and the rest react to them
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum ShotLabel {
+	Detect,
+	//health is only up to date after this
+	Damage,
+}
+
+impl Plugin for ShotPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_event::<ShotImpact>()
+			.add_event::<ShotHit>()
+			.init_resource::<Score>()
+			.add_system_set(
+				SystemSet::on_enter(AppState::Playing)
+					.with_system(create_shot_mesh_system)
+					.with_system(reset_score_system)
+			)
+			.add_system_set_to_stage(
+				FixedUpdateStage,
+				playing_tick_set()
+					.with_system(enemy_shot_system.label(TickLabel::Ai).after(AiLabel::Ability))
+					.with_system(shot_physics_system.label(TickLabel::Physics).after(TickLabel::Gravity))
+					.with_system(shot_hit_system.label(ShotLabel::Detect).label(TickLabel::Collision).after(TickLabel::Physics))
+					.with_system(shot_damage_system.label(ShotLabel::Damage).label(TickLabel::Collision).after(ShotLabel::Detect))
+					.with_system(shot_knockback_system.label(TickLabel::Collision).after(ShotLabel::Damage))
+					.with_system(shot_score_system.label(TickLabel::Collision).after(ShotLabel::Damage))
+					.with_system(remove_shot_system.after(TickLabel::Collision))
+					.with_system(stuck_shot_system.after(TickLabel::Collision))
+			);
+	}
+}
+
+//meshes are made at a radius of one so the shot's size can just scale them
 pub fn create_shot(
 	commands: &mut Commands,
 	mesh: Handle<Mesh>,
 	material: Handle<StandardMaterial>,
 	position: Vec3,
-	velocity: Vec3,
+	shot: Shot,
+	shot_physics: ShotPhysics,
 	sticky: Sticky,
-) {
-
+) -> Entity {
 	commands.spawn_bundle(PbrBundle {
 		mesh,
 		material,
-		transform: Transform::from_translation(position),
+		transform: Transform::from_translation(position).with_scale(Vec3::splat(shot.size)),
 		..PbrBundle::default()
 	})
 	.insert(sticky)
-	.insert(ShotPhysics {
-		velocity,
-	});
+	.insert(Interpolated::new(position))
+	.insert(shot)
+	.insert(shot_physics)
+	.id()
+}
+
+#[derive(Component, Copy, Clone, Debug)]
+pub struct Shot {
+	pub size: f32,
+	pub damage: u16,
+	//whoever fired it, they might not be around any more
+	pub owner: Entity,
+}
+
+//who a shot is allowed to hurt besides the other side, set per level
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub struct FriendlyFire {
+	//others on the shooter's side
+	#[serde(default)]
+	pub team: bool,
+	//the shooter themselves
+	#[serde(default)]
+	pub own: bool,
+}
+
+impl FriendlyFire {
+	pub fn allows(&self, owner: Entity, shooter_team: Sticky, target: Entity, target_team: Sticky) -> bool {
+		if target == owner {
+			self.own
+		} else if target_team == shooter_team {
+			self.team
+		} else {
+			true
+		}
+	}
 }
 
 #[derive(Component)]
 pub struct ShotPhysics {
-	velocity: Vec3,
+	pub velocity: Vec3,
+	pub gravity: f32,
+	pub response: ShotResponse,
+}
+
+//what a shot does when it hits a wall
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub enum ShotResponse {
+	#[default]
+	Despawn,
+	//keeps this much of its speed off the surface
+	Bounce {
+		restitution: f32,
+	},
+	//stays where it hit for this many seconds
+	Stick {
+		time: f32,
+	},
+}
+
+impl ShotResponse {
+	pub fn validate(&self) -> Result<(), String> {
+		match *self {
+			ShotResponse::Bounce { restitution } if !(0.0..=1.0).contains(&restitution) => {
+				Err("needs a restitution from 0 to 1".to_string())
+			},
+			ShotResponse::Stick { time } if time <= 0.0 => Err("needs to stick for longer than 0".to_string()),
+			_ => Ok(()),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub enum ProjectileShape {
+	#[default]
+	Sphere,
+	Cube,
+}
+
+//a shot hitting a wall, for effects and sounds, the shot might be gone by the time it's read
+#[derive(Copy, Clone, Debug)]
+pub struct ShotImpact {
+	pub shot: Entity,
+	pub wall: Entity,
+	pub point: Vec3,
+	pub normal: Vec3,
+	pub velocity: Vec3,
+	pub response: ShotResponse,
+}
+
+//a shot hitting a body, the shot is despawned at the end of the tick so it can still be looked up until then
+#[derive(Copy, Clone, Debug)]
+pub struct ShotHit {
+	pub shot: Entity,
+	pub target: Entity,
+	pub point: Vec3,
+	//the shot's owner and the side they're on
+	pub shooter: Entity,
+	pub team: Sticky,
+}
+
+//how the player's doing against the enemies this game
+#[derive(Default, Debug)]
+pub struct Score {
+	pub hits: u32,
+	pub kills: u32,
+}
+
+//a shot that's hit a wall and stuck to it, harmless until it goes
+#[derive(Component)]
+pub struct StuckShot {
+	pub remaining: f32,
 }
 
 pub struct ShotMesh {
 	pub shot_handle: Handle<Mesh>,
+	pub cube_handle: Handle<Mesh>,
+}
+
+impl ShotMesh {
+	pub fn get(&self, shape: ProjectileShape) -> Handle<Mesh> {
+		match shape {
+			ProjectileShape::Sphere => self.shot_handle.clone(),
+			ProjectileShape::Cube => self.cube_handle.clone(),
+		}
+	}
 }
 
 pub struct PlayerMaterial {
@@ -47,52 +212,156 @@ pub fn create_shot_mesh_system(
 	mut meshes: ResMut<Assets<Mesh>>,
 ) {
 	let mesh_handle = meshes.add(Mesh::from(shape::UVSphere {
-		radius: SHOT_SIZE,
+		radius: 1.0,
 		..shape::UVSphere::default()
 	}));
+	let cube_handle = meshes.add(Mesh::from(shape::Cube {
+		size: 2.0,
+	}));
 
 	commands.insert_resource(ShotMesh {
 		shot_handle: mesh_handle,
+		cube_handle,
 	});
 }
 
+//the enemies that might fire and the player they're firing at
+#[derive(SystemParam)]
+pub struct ShooterQueries<'w, 's> {
+	enemies: Query<'w, 's, (Entity, &'static Transform, &'static Archetype, &'static Handle<StandardMaterial>), EnemyHead>,
+	player: Query<'w, 's, (&'static Transform, &'static Physics), PlayerHead>,
+}
+
 pub fn enemy_shot_system(
 	mut commands: Commands,
-	enemy_mats: Res<EnemyMaterials>,
 	shot_mesh: Res<ShotMesh>,
-	query: Query<(&GlobalTransform, &EnemyColour), (With<Enemy>, With<Head>)>,
-	player_query: Query<&GlobalTransform, (With<Player>, With<Head>)>
+	queries: ShooterQueries,
+	director: Res<WaveDirector>,
+	colliders: Res<Colliders>,
+	tick_rate: Res<TickRate>,
+	mut rng: ResMut<GameRng>,
 ) {
-	let player_trans = player_query.get_single().unwrap();
-
-	for (trans, enemy_colour) in query.iter() {
-		//very scuffed
-		if fastrand::f32() < 0.002 {
-			let material = match enemy_colour {
-				EnemyColour::Red => enemy_mats.red.clone(),
-				EnemyColour::Green => enemy_mats.green.clone(),
-				EnemyColour::Blue => enemy_mats.blue.clone(),
+	let (player_trans, player_physics) = queries.player.get_single().unwrap();
+	//middle of the body rather than the head, there's more to hit
+	let target = player_trans.translation + Vec3::new(0.0, -3.5, 0.0);
+
+	for (entity, trans, archetype, material) in queries.enemies.iter() {
+		//still scuffed, but at least the rate is per second now
+		if rng.f32() < archetype.fire_rate * director.fire_rate * tick_rate.step() {
+			//no shooting through platforms, checked against the same point it aims at
+			if !colliders.line_of_sight(trans.translation, target) {
+				continue;
+			}
+			let accuracy = &archetype.accuracy;
+			let velocity = match ballistic_velocity(
+				trans.translation,
+				target,
+				player_physics.velocity * accuracy.lead,
+				archetype.shot_speed,
+				archetype.shot_gravity,
+				accuracy.lob,
+			) {
+				Some(velocity) => apply_spread(velocity, accuracy.spread, &mut rng),
+				//can't reach, save it for when the player's closer
+				None => continue,
 			};
 			create_shot(
 				&mut commands,
 				shot_mesh.shot_handle.clone(),
-				material,
+				material.clone(),
 				trans.translation,
-				player_trans.translation - trans.translation,
+				Shot {
+					size: SHOT_SIZE,
+					damage: archetype.shot_damage,
+					owner: entity,
+				},
+				ShotPhysics {
+					velocity,
+					gravity: archetype.shot_gravity,
+					response: archetype.shot_response,
+				},
 				Sticky::Enemy,
 			);
 		}
 	}
 }
 
+//moves shots along and stops them at the first wall in the way so fast ones can't go through
 pub fn shot_physics_system(
-	mut query: Query<(&mut Transform, &mut ShotPhysics)>,
-	time: Res<Time>,
+	mut commands: Commands,
+	mut query: Query<(Entity, &mut Transform, &Shot, &mut ShotPhysics)>,
+	colliders: Res<Colliders>,
+	mut impact_events: EventWriter<ShotImpact>,
+	tick_rate: Res<TickRate>,
+) {
+	let delta = tick_rate.step();
+	for (entity, mut trans, shot, mut shot_physics) in query.iter_mut() {
+		let step = shot_physics.velocity * delta;
+		match colliders.raycast(trans.translation, step, step.length()) {
+			Some(hit) => {
+				impact_events.send(ShotImpact {
+					shot: entity,
+					wall: hit.entity,
+					point: hit.point,
+					normal: hit.normal,
+					velocity: shot_physics.velocity,
+					response: shot_physics.response,
+				});
+				respond_to_impact(&mut commands, entity, &mut trans, shot, &mut shot_physics, hit);
+			},
+			None => {
+				trans.translation += step;
+				shot_physics.velocity.y -= shot_physics.gravity * delta;
+			},
+		}
+	}
+}
+
+fn respond_to_impact(
+	commands: &mut Commands,
+	entity: Entity,
+	trans: &mut Transform,
+	shot: &Shot,
+	shot_physics: &mut ShotPhysics,
+	hit: RayHit,
+) {
+	//sitting just off the surface so the next tick doesn't start inside it
+	let surface = hit.point + hit.normal * shot.size;
+	match shot_physics.response {
+		//started inside a wall, nothing sensible to bounce or stick off
+		_ if hit.normal == Vec3::ZERO => commands.entity(entity).despawn(),
+		ShotResponse::Despawn => commands.entity(entity).despawn(),
+		ShotResponse::Bounce { restitution } => {
+			let into = shot_physics.velocity.dot(hit.normal);
+			if -into * restitution < MIN_BOUNCE_SPEED {
+				commands.entity(entity).despawn();
+				return;
+			}
+			//flip the part going into the surface and lose some of it
+			shot_physics.velocity -= hit.normal * into * (1.0 + restitution);
+			trans.translation = surface;
+		},
+		ShotResponse::Stick { time } => {
+			trans.translation = surface;
+			commands.entity(entity)
+				.remove::<ShotPhysics>()
+				.insert(StuckShot {
+					remaining: time,
+				});
+		},
+	}
+}
+
+pub fn stuck_shot_system(
+	mut commands: Commands,
+	mut query: Query<(Entity, &mut StuckShot)>,
+	tick_rate: Res<TickRate>,
 ) {
-	let delta = time.delta_seconds();
-	for (mut trans, mut shot_physics) in query.iter_mut() {
-		trans.translation += shot_physics.velocity * delta;
-		shot_physics.velocity.y -= 0.1;
+	for (entity, mut stuck) in query.iter_mut() {
+		stuck.remaining -= tick_rate.step();
+		if stuck.remaining <= 0.0 {
+			commands.entity(entity).despawn();
+		}
 	}
 }
 
@@ -107,42 +376,245 @@ pub fn remove_shot_system(
 	}
 }
 
-pub fn shot_sticky_collision_check_system(
+//any body a shot can hit, shots themselves excluded
+type Target = (With<Head>, Without<ShotPhysics>);
+
+//works out what each shot touched this tick, what happens because of it is left to the systems after
+pub fn shot_hit_system(
+	mut commands: Commands,
+	shot_query: Query<(Entity, &Transform, &Shot, &Sticky), With<ShotPhysics>>,
+	target_query: Query<(Entity, &Transform, &Sticky, Option<&Invulnerable>), Target>,
+	levels: Res<Assets<Level>>,
+	current_level: Res<CurrentLevel>,
+	mut hit_events: EventWriter<ShotHit>,
+) {
+	let friendly_fire = levels.get(&current_level.handle).unwrap().friendly_fire;
+	let mut used = Vec::new();
+	for (target, target_trans, target_team, invulnerable) in target_query.iter() {
+		let target_hitbox = (target_trans.translation + Vec3::new(0.0, -3.5, 0.0), Vec3::new(STICKY_SIZE, 6.0, STICKY_SIZE));
+
+		//one hit each a tick at most, and a shot only hits one thing
+		//shots that aren't allowed to hurt this target go straight through it
+		let hit = shot_query.iter()
+			.filter(|(shot, _, _, _)| !used.contains(shot))
+			.filter(|(_, _, shot, team)| friendly_fire.allows(shot.owner, **team, target, *target_team))
+			.find(|(_, shot_trans, shot, _)| detect_collision(target_hitbox, (shot_trans.translation, Vec3::splat(shot.size))));
+		if let Some((shot, shot_trans, shot_info, team)) = hit {
+			used.push(shot);
+			commands.entity(shot).despawn();
+			//just been hit, the shot's used up but does nothing
+			if invulnerable.is_some_and(|invulnerable| invulnerable.remaining > 0.0) {
+				continue;
+			}
+			hit_events.send(ShotHit {
+				shot,
+				target,
+				point: shot_trans.translation,
+				shooter: shot_info.owner,
+				team: *team,
+			});
+		}
+	}
+}
+
+pub fn shot_damage_system(
 	mut commands: Commands,
-	shot_query: Query<(Entity, &Transform), With<ShotPhysics>>,
-	mut sticky_query: Query<(&mut Transform, Entity, Option<&mut Health>, Option<&mut Physics>), (With<Head>, With<Sticky>, Without<ShotPhysics>)>,
-	mut enemy_num: ResMut<EnemyNum>,
-	mut app_state: ResMut<State<AppState>>,
+	mut hit_events: EventReader<ShotHit>,
+	shot_query: Query<&Shot>,
+	mut target_query: Query<(&Sticky, &mut Health, Option<&mut Invulnerable>)>,
+) {
+	for hit in hit_events.iter() {
+		let (shot, (sticky, mut health, invulnerable)) = match (shot_query.get(hit.shot), target_query.get_mut(hit.target)) {
+			(Ok(shot), Ok(target)) => (shot, target),
+			_ => continue,
+		};
+		health.amount = health.amount.saturating_sub(shot.damage);
+		if let Some(mut invulnerable) = invulnerable {
+			invulnerable.remaining = HIT_INVULNERABILITY;
+		}
+		//the player stays around so the lose rules can decide what happens
+		if *sticky == Sticky::Enemy && health.amount == 0 {
+			commands.entity(hit.target).despawn_recursive();
+		}
+	}
+}
+
+//whatever a hit can move, with its health if it has any
+type KnockbackTarget<'a> = (Entity, &'a mut Transform, &'a mut Interpolated, &'a Sticky, &'a mut Physics, Option<&'a Health>);
+
+//the player gets thrown about, an enemy that's still standing blinks somewhere else
+pub fn shot_knockback_system(
+	mut hit_events: EventReader<ShotHit>,
+	mut target_query: Query<KnockbackTarget, With<Head>>,
+	collision_query: Query<&Transform, (With<Collision>, Without<Head>)>,
+	mut rng: ResMut<GameRng>,
 ) {
-	for (mut sticky_trans, sticky_entity, enemy_health, player_physics) in sticky_query.iter_mut() {
-		for (shot_entity, shot_trans) in shot_query.iter() {
-			let sticky_hitbox_trans = sticky_trans.translation + Vec3::new(0.0, -3.5, 0.0);
-			let sticky_hitbox_scale = Vec3::new(STICKY_SIZE, 6.0, STICKY_SIZE);
-
-			if detect_collision((sticky_hitbox_trans, sticky_hitbox_scale), (shot_trans.translation, Vec3::new(SHOT_SIZE, SHOT_SIZE, SHOT_SIZE))) {
-				commands.entity(shot_entity).despawn();
-				if player_physics.is_some() {
-					let mut physics = player_physics.unwrap();
-					physics.velocity.x += (fastrand::f32() - 0.5) * 10.0;
-					physics.velocity.y += 30.0;
-					physics.velocity.z += (fastrand::f32() - 0.5) * 10.0;
-				} else {
-					let mut health = enemy_health.unwrap();
-					health.amount -= 1;
-					if health.amount == 0 {
-						commands.entity(sticky_entity).despawn_recursive();
-						enemy_num.number -= 1;
-						if enemy_num.number == 0 {
-							app_state.set(AppState::Win).unwrap();
-						}
-					} else {
-						sticky_trans.translation.x = (fastrand::f32() - 0.5) * 100.0;
-						sticky_trans.translation.z = (fastrand::f32() - 0.5) * 100.0;
-					}
+	let mut hits = hit_events.iter().peekable();
+	if hits.peek().is_none() {
+		return;
+	}
+	let walls: Vec<(Vec3, Vec3)> = collision_query.iter()
+		.map(|trans| (trans.translation, trans.scale))
+		.collect();
+
+	for hit in hits {
+		//where everyone is now, for keeping teleports away from them
+		let others: Vec<(Vec3, bool)> = target_query.iter()
+			.filter(|(entity, _, _, _, _, _)| *entity != hit.target)
+			.map(|(_, trans, _, sticky, _, _)| (trans.translation, *sticky == Sticky::Enemy))
+			.collect();
+
+		let (_, mut trans, mut interpolated, sticky, mut physics, health) = match target_query.get_mut(hit.target) {
+			Ok(target) => target,
+			Err(_) => continue,
+		};
+		match sticky {
+			Sticky::Player => {
+				physics.velocity.x += rng.centred() * 10.0;
+				physics.velocity.y += 30.0;
+				physics.velocity.z += rng.centred() * 10.0;
+			},
+			//dead ones are despawned at the end of the tick
+			Sticky::Enemy if health.is_none_or(|health| health.amount == 0) => {},
+			Sticky::Enemy => {
+				let avoid = spacing(
+					others.iter().filter(|(_, enemy)| !enemy).map(|(point, _)| *point),
+					others.iter().filter(|(_, enemy)| *enemy).map(|(point, _)| *point),
+				);
+				if let Some(position) = place_on_platform(&walls, &avoid, None, &mut rng) {
+					interpolated.snap(&mut trans, position);
+					physics.velocity = Vec3::ZERO;
 				}
-				break;
+			},
+		}
+	}
+}
+
+pub fn shot_score_system(
+	mut hit_events: EventReader<ShotHit>,
+	target_query: Query<(&Sticky, Option<&Health>)>,
+	mut score: ResMut<Score>,
+) {
+	for hit in hit_events.iter() {
+		if hit.team != Sticky::Player {
+			continue;
+		}
+		if let Ok((Sticky::Enemy, health)) = target_query.get(hit.target) {
+			score.hits += 1;
+			if health.is_some_and(|health| health.amount == 0) {
+				score.kills += 1;
+				info!("{} kills", score.kills);
 			}
 		}
 	}
 }
 
+pub fn reset_score_system(
+	mut score: ResMut<Score>,
+) {
+	*score = Score::default();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bevy::app::Events;
+	use bevy::math::const_vec3;
+
+	//top face at zero, wide enough that nothing misses it
+	const FLOOR: (Vec3, Vec3) = (const_vec3!([0.0, -0.5, 0.0]), const_vec3!([10.0, 1.0, 10.0]));
+
+	fn world_with(response: ShotResponse, position: Vec3, velocity: Vec3) -> (World, Entity) {
+		let mut world = World::new();
+		world.insert_resource(TickRate::default());
+		world.insert_resource(Events::<ShotImpact>::default());
+		let floor = world.spawn().id();
+		world.insert_resource(Colliders {
+			boxes: vec![(floor, FLOOR.0, FLOOR.1)],
+		});
+		let shot = world.spawn()
+			.insert(Transform::from_translation(position))
+			.insert(Shot {
+				size: SHOT_SIZE,
+				damage: 1,
+				owner: floor,
+			})
+			.insert(ShotPhysics {
+				velocity,
+				gravity: 0.0,
+				response,
+			})
+			.id();
+		(world, shot)
+	}
+
+	fn ticker() -> SystemStage {
+		SystemStage::single_threaded()
+			.with_system(shot_physics_system)
+			.with_system(stuck_shot_system)
+	}
+
+	#[test]
+	fn bounce_reflects_off_the_surface() {
+		let (mut world, shot) = world_with(ShotResponse::Bounce { restitution: 0.5 }, Vec3::new(0.0, 0.5, 0.0), Vec3::new(6.0, -60.0, 0.0));
+		let mut stage = ticker();
+
+		stage.run(&mut world);
+		//along the floor is kept, into it comes back out at half the speed
+		let velocity = world.get::<ShotPhysics>(shot).unwrap().velocity;
+		assert!(velocity.abs_diff_eq(Vec3::new(6.0, 30.0, 0.0), 0.001), "bounced to {}", velocity);
+		let y = world.get::<Transform>(shot).unwrap().translation.y;
+		assert!((y - SHOT_SIZE).abs() < 0.001, "left at {} instead of on the surface", y);
+		assert_eq!(world.get_resource::<Events<ShotImpact>>().unwrap().iter_current_update_events().count(), 1);
+
+		stage.run(&mut world);
+		assert!(world.get::<Transform>(shot).unwrap().translation.y > y);
+	}
+
+	#[test]
+	fn stuck_shot_stays_put() {
+		let (mut world, shot) = world_with(ShotResponse::Stick { time: 1.0 }, Vec3::new(0.0, 0.5, 0.0), Vec3::new(6.0, -60.0, 0.0));
+		let mut stage = ticker();
+
+		stage.run(&mut world);
+		assert!(world.get::<ShotPhysics>(shot).is_none());
+		let stuck_at = world.get::<Transform>(shot).unwrap().translation;
+		assert!((stuck_at.y - SHOT_SIZE).abs() < 0.001, "stuck at {} instead of on the surface", stuck_at);
+
+		//just under a second of ticks, it hasn't moved and is still there
+		for _ in 0..50 {
+			stage.run(&mut world);
+			assert_eq!(world.get::<Transform>(shot).unwrap().translation, stuck_at);
+		}
+		for _ in 0..20 {
+			stage.run(&mut world);
+		}
+		assert!(world.get_entity(shot).is_none());
+	}
+
+	#[test]
+	fn friendly_fire_settings() {
+		let owner = Entity::from_raw(0);
+		let ally = Entity::from_raw(1);
+		let enemy = Entity::from_raw(2);
+		let targets = [(owner, Sticky::Player), (ally, Sticky::Player), (enemy, Sticky::Enemy)];
+
+		//team, own, then whether the owner, an ally and an enemy can be hit
+		let table = [
+			(false, false, [false, false, true]),
+			(true, false, [false, true, true]),
+			(false, true, [true, false, true]),
+			(true, true, [true, true, true]),
+		];
+		for (team, own, expected) in table {
+			let friendly_fire = FriendlyFire { team, own };
+			for ((target, target_team), allowed) in targets.into_iter().zip(expected) {
+				assert_eq!(
+					friendly_fire.allows(owner, Sticky::Player, target, target_team),
+					allowed,
+					"{:?} hitting {:?}", friendly_fire, target,
+				);
+			}
+		}
+	}
+}
@@ -1,7 +1,33 @@
-use crate::{Player, Head};
+use crate::PlayerHead;
+use crate::map::{Level, CurrentLevel};
+use crate::enemy::Health;
+use crate::tick::{TickLabel, FixedUpdateStage, playing_tick_set};
+use serde::Deserialize;
 use bevy::prelude::*;
 
-const LOSE_Y: f32 = -50.0;
+pub const LOSE_Y: f32 = -50.0;
+
+//what ends the game for the player, set per level
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub enum LoseRule {
+	//running out of health, falling off just costs some and puts them back at the spawn
+	Health,
+	//falling off, health runs out but doesn't matter
+	Falling,
+	//either one
+	#[default]
+	Both,
+}
+
+impl LoseRule {
+	pub fn uses_health(&self) -> bool {
+		matches!(self, LoseRule::Health | LoseRule::Both)
+	}
+
+	pub fn uses_falling(&self) -> bool {
+		matches!(self, LoseRule::Falling | LoseRule::Both)
+	}
+}
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum AppState {
@@ -11,14 +37,41 @@ pub enum AppState {
 	Win,
 }
 
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_state(AppState::Loading)
+			.add_system_set_to_stage(
+				FixedUpdateStage,
+				playing_tick_set()
+					.with_system(check_lose_system.label(TickLabel::Rules).after(TickLabel::Collision))
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::GameOver)
+					.with_system(restart_game_system)
+			)
+			.add_system_set(
+				SystemSet::on_update(AppState::Win)
+					.with_system(restart_game_system)
+			);
+	}
+}
+
 pub fn check_lose_system(
 	mut app_state: ResMut<State<AppState>>,
-	player_query: Query<&Transform, (With<Player>, With<Head>)>,
+	player_query: Query<(&Transform, &Health), PlayerHead>,
+	levels: Res<Assets<Level>>,
+	current_level: Res<CurrentLevel>,
 ) {
-	let player_trans = player_query.get_single().unwrap();
+	let level = levels.get(&current_level.handle).unwrap();
+	let (player_trans, health) = player_query.get_single().unwrap();
 
-	if player_trans.translation.y < LOSE_Y {
-		app_state.set(AppState::GameOver).unwrap();
+	let fell = level.lose.uses_falling() && player_trans.translation.y < LOSE_Y;
+	let died = level.lose.uses_health() && health.amount == 0;
+	if fell || died {
+		//overwrite as a second tick in the same frame can get here before the state changes
+		app_state.overwrite_set(AppState::GameOver).unwrap();
 	}
 }
 
@@ -0,0 +1,171 @@
+use crate::state::AppState;
+use bevy::prelude::*;
+use bevy::core::{FixedTimestep, FixedTimesteps};
+use bevy::ecs::schedule::ShouldRun;
+use bevy::transform::TransformSystem;
+
+//gameplay runs at a fixed rate in its own stage so every machine simulates the same steps,
+//rendering then blends between the last two steps
+pub const FIXED_TIMESTEP: &str = "fixed_timestep";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct FixedUpdateStage;
+
+//order of a tick, restore and store wrap everything else
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum TickLabel {
+	Restore,
+	Sample,
+	Input,
+	//enemies react to what the player did this tick
+	Ai,
+	Gravity,
+	Physics,
+	Collision,
+	Rules,
+	Store,
+}
+
+pub struct TickPlugin;
+
+impl Plugin for TickPlugin {
+	fn build(&self, app: &mut App) {
+		let tick_rate = app.world.get_resource_or_insert_with(TickRate::default);
+		let fixed_stage = if tick_rate.lockstep {
+			SystemStage::parallel()
+		} else {
+			SystemStage::parallel()
+				.with_run_criteria(FixedTimestep::step(1.0 / tick_rate.hz).with_label(FIXED_TIMESTEP))
+		};
+
+		app.init_resource::<TickCount>()
+			.add_stage_after(CoreStage::Update, FixedUpdateStage, fixed_stage)
+			.add_system_set_to_stage(
+				FixedUpdateStage,
+				SystemSet::new()
+					.with_run_criteria(run_if_playing)
+					.with_system(restore_sim_transforms.label(TickLabel::Restore))
+					.with_system(store_sim_transforms.label(TickLabel::Store))
+			)
+			.add_system_to_stage(
+				CoreStage::PostUpdate,
+				interpolate_transforms.before(TransformSystem::TransformPropagate)
+			);
+	}
+}
+
+//set for gameplay systems to add to the fixed stage with
+pub fn playing_tick_set() -> SystemSet {
+	SystemSet::new()
+		.with_run_criteria(run_if_playing)
+		.after(TickLabel::Restore)
+		.before(TickLabel::Store)
+}
+
+pub struct TickRate {
+	pub hz: f64,
+	//step once every app update instead of following the clock, for headless runs
+	pub lockstep: bool,
+}
+
+impl Default for TickRate {
+	fn default() -> Self {
+		TickRate {
+			hz: 60.0,
+			lockstep: false,
+		}
+	}
+}
+
+//ticks simulated so far, replays line up on this
+#[derive(Default)]
+pub struct TickCount {
+	pub count: u64,
+}
+
+impl TickRate {
+	pub fn step(&self) -> f32 {
+		(1.0 / self.hz) as f32
+	}
+}
+
+//where the simulation had something the last two ticks, rendering is smoothed between them
+#[derive(Component)]
+pub struct Interpolated {
+	previous: Vec3,
+	current: Vec3,
+	previous_rotation: Quat,
+	current_rotation: Quat,
+}
+
+impl Interpolated {
+	pub fn new(position: Vec3) -> Interpolated {
+		Interpolated::from_transform(&Transform::from_translation(position))
+	}
+
+	pub fn from_transform(transform: &Transform) -> Interpolated {
+		Interpolated {
+			previous: transform.translation,
+			current: transform.translation,
+			previous_rotation: transform.rotation,
+			current_rotation: transform.rotation,
+		}
+	}
+
+	//moves straight there without rendering the way between, for teleports and respawns
+	pub fn snap(&mut self, transform: &mut Transform, position: Vec3) {
+		transform.translation = position;
+		self.previous = position;
+		self.current = position;
+	}
+}
+
+//can't use the state's own run criteria here as the state driver lives in another stage
+pub fn run_if_playing(
+	app_state: Res<State<AppState>>,
+) -> ShouldRun {
+	if *app_state.current() == AppState::Playing {
+		ShouldRun::Yes
+	} else {
+		ShouldRun::No
+	}
+}
+
+//put the simulated position and rotation back before stepping, rendering may have moved them, and count the tick
+pub fn restore_sim_transforms(
+	mut query: Query<(&mut Transform, &mut Interpolated)>,
+	mut tick_count: ResMut<TickCount>,
+) {
+	tick_count.count += 1;
+
+	for (mut transform, mut interpolated) in query.iter_mut() {
+		transform.translation = interpolated.current;
+		transform.rotation = interpolated.current_rotation;
+		interpolated.previous = interpolated.current;
+		interpolated.previous_rotation = interpolated.current_rotation;
+	}
+}
+
+pub fn store_sim_transforms(
+	mut query: Query<(&Transform, &mut Interpolated)>,
+) {
+	for (transform, mut interpolated) in query.iter_mut() {
+		interpolated.current = transform.translation;
+		interpolated.current_rotation = transform.rotation;
+	}
+}
+
+pub fn interpolate_transforms(
+	fixed_timesteps: Res<FixedTimesteps>,
+	mut query: Query<(&mut Transform, &Interpolated)>,
+) {
+	let amount_through = match fixed_timesteps.get(FIXED_TIMESTEP) {
+		Some(state) => state.overstep_percentage().min(1.0) as f32,
+		None => return,
+	};
+
+	for (mut transform, interpolated) in query.iter_mut() {
+		transform.translation = interpolated.previous.lerp(interpolated.current, amount_through);
+		transform.rotation = interpolated.previous_rotation.slerp(interpolated.current_rotation, amount_through);
+	}
+}
@@ -0,0 +1,228 @@
+use crate::{PlayerHead, EnemyHead};
+use crate::map::{Level, CurrentLevel, Collision};
+use crate::archetype::{EnemyArchetypes, CurrentArchetypes};
+use crate::enemy::{EnemyAssets, spawn_enemy};
+use crate::state::AppState;
+use crate::rng::GameRng;
+use crate::placement::{place_on_platform, spacing};
+use crate::tick::{TickRate, TickLabel, FixedUpdateStage, playing_tick_set};
+use serde::Deserialize;
+use bevy::prelude::*;
+use bevy::asset::Asset;
+use bevy::ecs::system::SystemParam;
+use std::marker::PhantomData;
+
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<WaveDirector>()
+			.add_system(check_wave_archetypes_system)
+			.add_system_set(
+				SystemSet::on_enter(AppState::Playing)
+					.with_system(start_waves_system)
+			)
+			.add_system_set_to_stage(
+				FixedUpdateStage,
+				playing_tick_set()
+					.with_system(wave_director_system.label(TickLabel::Rules).after(TickLabel::Collision))
+			);
+	}
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Wave {
+	pub count: u16,
+	//archetype names and how likely each one is to be picked
+	pub mix: Vec<(String, f32)>,
+	//seconds between each enemy turning up
+	pub spawn_delay: f32,
+	//times every enemy's own fire rate
+	#[serde(default = "default_fire_rate")]
+	pub fire_rate: f32,
+}
+
+fn default_fire_rate() -> f32 {
+	1.0
+}
+
+//keeps going past the last wave, each one bigger and angrier than the one before
+#[derive(Clone, Debug, Deserialize)]
+pub struct Endless {
+	pub extra_enemies: u16,
+	pub extra_fire_rate: f32,
+}
+
+impl Wave {
+	pub fn validate(&self) -> Result<(), String> {
+		if self.count == 0 {
+			return Err("has no enemies".to_string());
+		}
+		if self.mix.is_empty() || self.mix.iter().all(|(_, weight)| *weight <= 0.0) {
+			return Err("has nothing to pick enemies from".to_string());
+		}
+		Ok(())
+	}
+
+	fn pick<'a>(&'a self, rng: &mut GameRng) -> &'a str {
+		let total: f32 = self.mix.iter().map(|(_, weight)| weight.max(0.0)).sum();
+		let mut choice = rng.f32() * total;
+		for (name, weight) in self.mix.iter() {
+			choice -= weight.max(0.0);
+			if choice < 0.0 {
+				return name;
+			}
+		}
+		&self.mix.last().unwrap().0
+	}
+}
+
+//the wave at an index, made up from the last one when the level is endless
+pub fn wave_at(level: &Level, index: usize) -> Option<Wave> {
+	if let Some(wave) = level.waves.get(index) {
+		return Some(wave.clone());
+	}
+	let endless = level.endless.as_ref()?;
+	let last = level.waves.last()?;
+	//long endless runs just stop growing rather than wrap
+	let past_end = u16::try_from(index + 1 - level.waves.len()).unwrap_or(u16::MAX);
+	Some(Wave {
+		count: last.count.saturating_add(endless.extra_enemies.saturating_mul(past_end)),
+		fire_rate: last.fire_rate + endless.extra_fire_rate * past_end as f32,
+		..last.clone()
+	})
+}
+
+//names in a wave's mix that no archetype has, checked whenever the level or archetypes (re)load
+pub fn check_wave_archetypes_system(
+	mut level_events: EventReader<AssetEvent<Level>>,
+	mut archetype_events: EventReader<AssetEvent<EnemyArchetypes>>,
+	levels: Res<Assets<Level>>,
+	current_level: Res<CurrentLevel>,
+	archetype_assets: Res<Assets<EnemyArchetypes>>,
+	current_archetypes: Res<CurrentArchetypes>,
+) {
+	let level_changed = level_events.iter().any(|event| loaded(event, &current_level.handle));
+	let archetypes_changed = archetype_events.iter().any(|event| loaded(event, &current_archetypes.handle));
+	if !level_changed && !archetypes_changed {
+		return;
+	}
+	let (level, archetypes) = match (levels.get(&current_level.handle), archetype_assets.get(&current_archetypes.handle)) {
+		(Some(level), Some(archetypes)) => (level, archetypes),
+		_ => return,
+	};
+
+	for (index, wave) in level.waves.iter().enumerate() {
+		for (name, _) in wave.mix.iter() {
+			if archetypes.get(name).is_none() {
+				warn!("{}: wave {} wants unknown archetype \"{}\"", current_level.path, index + 1, name);
+			}
+		}
+	}
+}
+
+fn loaded<T: Asset>(event: &AssetEvent<T>, current: &Handle<T>) -> bool {
+	match event {
+		AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle == current,
+		AssetEvent::Removed { .. } => false,
+	}
+}
+
+#[derive(Default)]
+pub struct WaveDirector {
+	//counting from 0
+	pub wave: usize,
+	//still to spawn this wave
+	pub remaining: u16,
+	pub spawn_timer: f32,
+	pub fire_rate: f32,
+}
+
+impl WaveDirector {
+	fn start(&mut self, index: usize, wave: &Wave) {
+		self.wave = index;
+		self.remaining = wave.count;
+		self.spawn_timer = 0.0;
+		self.fire_rate = wave.fire_rate;
+		info!("wave {}", index + 1);
+	}
+}
+
+pub fn start_waves_system(
+	mut director: ResMut<WaveDirector>,
+	levels: Res<Assets<Level>>,
+	current_level: Res<CurrentLevel>,
+) {
+	let level = levels.get(&current_level.handle).unwrap();
+	director.start(0, &level.waves[0]);
+}
+
+//where everyone is, for picking somewhere to spawn and telling when a wave's cleared
+#[derive(SystemParam)]
+pub struct WaveQueries<'w, 's> {
+	enemies: Query<'w, 's, &'static Transform, EnemyHead>,
+	players: Query<'w, 's, &'static Transform, PlayerHead>,
+	walls: Query<'w, 's, &'static Transform, With<Collision>>,
+}
+
+//what the waves are made from and what the enemies in them look like
+#[derive(SystemParam)]
+pub struct WaveAssets<'w, 's> {
+	levels: Res<'w, Assets<Level>>,
+	current_level: Res<'w, CurrentLevel>,
+	archetypes: Res<'w, Assets<EnemyArchetypes>>,
+	current_archetypes: Res<'w, CurrentArchetypes>,
+	enemy_assets: ResMut<'w, EnemyAssets>,
+	materials: ResMut<'w, Assets<StandardMaterial>>,
+	#[system_param(ignore)]
+	marker: PhantomData<&'s ()>,
+}
+
+pub fn wave_director_system(
+	mut commands: Commands,
+	mut director: ResMut<WaveDirector>,
+	queries: WaveQueries,
+	mut assets: WaveAssets,
+	mut app_state: ResMut<State<AppState>>,
+	tick_rate: Res<TickRate>,
+	mut rng: ResMut<GameRng>,
+) {
+	let level = assets.levels.get(&assets.current_level.handle).unwrap();
+	let archetypes = assets.archetypes.get(&assets.current_archetypes.handle).unwrap();
+	let wave = match wave_at(level, director.wave) {
+		Some(wave) => wave,
+		None => return,
+	};
+
+	if director.remaining > 0 {
+		director.spawn_timer -= tick_rate.step();
+		if director.spawn_timer <= 0.0 {
+			let name = wave.pick(&mut rng);
+			match archetypes.get(name) {
+				Some(archetype) => {
+					let walls: Vec<(Vec3, Vec3)> = queries.walls.iter()
+						.map(|trans| (trans.translation, trans.scale))
+						.collect();
+					let avoid = spacing(
+						queries.players.iter().map(|trans| trans.translation),
+						queries.enemies.iter().map(|trans| trans.translation),
+					);
+					//the level's own spawns if the floor is too crowded
+					let position = place_on_platform(&walls, &avoid, None, &mut rng)
+						.unwrap_or_else(|| level.enemy_spawns[rng.usize(level.enemy_spawns.len())]);
+					spawn_enemy(&mut commands, &mut assets.enemy_assets, &mut assets.materials, archetype, position);
+				},
+				None => error!("wave {} wants unknown archetype \"{}\"", director.wave + 1, name),
+			}
+			director.remaining -= 1;
+			director.spawn_timer = wave.spawn_delay;
+		}
+	//spawns land at the end of the tick, so a wave can't look cleared the tick its last enemy appears
+	} else if queries.enemies.is_empty() {
+		let next = director.wave + 1;
+		match wave_at(level, next) {
+			Some(next_wave) => director.start(next, &next_wave),
+			None => app_state.overwrite_set(AppState::Win).unwrap(),
+		}
+	}
+}
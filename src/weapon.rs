@@ -0,0 +1,321 @@
+use crate::{Head, Sticky};
+use crate::map::{Level, CurrentLevel};
+use crate::shot::{Shot, ShotImpact, ShotHit, ShotResponse, ProjectileShape};
+use crate::physics::Physics;
+use crate::controls::PlayerInput;
+use crate::tick::{TickLabel, FixedUpdateStage, playing_tick_set};
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::marker::PhantomData;
+
+pub const WEAPONS_PATH: &str = "player.weapons.ron";
+
+pub struct WeaponPlugin;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct WeaponLabel;
+
+impl Plugin for WeaponPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_asset::<WeaponDefinitions>()
+			.init_asset_loader::<WeaponLoader>()
+			.add_startup_system(load_weapons)
+			.add_system_set_to_stage(
+				FixedUpdateStage,
+				playing_tick_set()
+					.with_system(clamp_weapon_system.before(WeaponLabel).after(TickLabel::Sample))
+					.with_system(select_weapon_system.label(WeaponLabel).label(TickLabel::Input).after(TickLabel::Sample))
+					.with_system(explosion_system.after(TickLabel::Collision).before(TickLabel::Rules))
+			);
+	}
+}
+
+//the player's weapons, number keys pick them in the order they're listed
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "29447470-678f-45eb-b0b0-d471a18907fa"]
+pub struct WeaponDefinitions {
+	pub weapons: Vec<WeaponDefinition>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WeaponDefinition {
+	pub name: String,
+	//seconds between shots
+	pub cooldown: f32,
+	#[serde(default)]
+	pub trigger: Trigger,
+	//shots fired at once, fanned out evenly across spread radians
+	#[serde(default = "default_pellets")]
+	pub pellets: u8,
+	#[serde(default)]
+	pub spread: f32,
+	pub projectile: Projectile,
+	//knocks everything nearby away when it hits a wall or a body
+	#[serde(default)]
+	pub explosion: Option<Explosion>,
+}
+
+fn default_pellets() -> u8 {
+	1
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Projectile {
+	#[serde(default)]
+	pub shape: ProjectileShape,
+	pub size: f32,
+	pub speed: f32,
+	pub gravity: f32,
+	pub damage: u16,
+	#[serde(default)]
+	pub response: ShotResponse,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub enum Trigger {
+	//one shot per click
+	#[default]
+	Single,
+	//keeps firing while held
+	Automatic,
+	//builds up while held and fires on release, up to max_multiplier times the damage and size after time seconds
+	Charged {
+		time: f32,
+		max_multiplier: f32,
+	},
+}
+
+#[derive(Component, Copy, Clone, Debug, Deserialize)]
+pub struct Explosion {
+	pub radius: f32,
+	//speed given to something right in the middle, dropping off to nothing at the edge
+	pub knockback: f32,
+}
+
+impl WeaponDefinitions {
+	fn validate(&self) -> Result<(), String> {
+		if self.weapons.is_empty() {
+			return Err("no weapons".to_string());
+		}
+		for (index, weapon) in self.weapons.iter().enumerate() {
+			if self.weapons[..index].iter().any(|other| other.name == weapon.name) {
+				return Err(format!("weapon \"{}\" is defined twice", weapon.name));
+			}
+			weapon.validate()
+				.map_err(|err| format!("weapon \"{}\" {}", weapon.name, err))?;
+		}
+		Ok(())
+	}
+}
+
+impl WeaponDefinition {
+	fn validate(&self) -> Result<(), String> {
+		if self.cooldown < 0.0 {
+			return Err("has a negative cooldown".to_string());
+		}
+		if self.pellets == 0 {
+			return Err("fires no pellets".to_string());
+		}
+		if self.projectile.size <= 0.0 || self.projectile.speed <= 0.0 {
+			return Err("needs a projectile size and speed above 0".to_string());
+		}
+		if let Trigger::Charged { time, max_multiplier } = self.trigger {
+			if time <= 0.0 || max_multiplier < 1.0 {
+				return Err("needs a charge time above 0 and a multiplier of at least 1".to_string());
+			}
+		}
+		if let Some(explosion) = self.explosion {
+			if explosion.radius <= 0.0 {
+				return Err("needs an explosion radius above 0".to_string());
+			}
+		}
+		self.projectile.response.validate()
+			.map_err(|err| format!("projectile {}", err))
+	}
+
+	//sideways angle of each pellet from straight ahead
+	pub fn pellet_angles(&self) -> impl Iterator<Item = f32> + '_ {
+		(0..self.pellets).map(|pellet| {
+			if self.pellets == 1 {
+				0.0
+			} else {
+				self.spread * (pellet as f32 / (self.pellets - 1) as f32 - 0.5)
+			}
+		})
+	}
+}
+
+//the player's current weapon and what it's doing
+#[derive(Component, Default)]
+pub struct Weapon {
+	//index into the definitions
+	pub selected: usize,
+	pub cooldown: f32,
+	//seconds held so far on a charged weapon, none when it isn't being held
+	pub charge: Option<f32>,
+}
+
+impl Weapon {
+	//whether this tick's input fires the weapon, and how powerful the shot is if so
+	pub fn pull_trigger(&mut self, definition: &WeaponDefinition, input: &PlayerInput, delta: f32) -> Option<f32> {
+		if self.cooldown > 0.0 {
+			return None;
+		}
+		//a click between two ticks still counts as holding it for one
+		let held = input.shoot || input.shoot_held;
+		match definition.trigger {
+			Trigger::Single => input.shoot.then_some(1.0),
+			Trigger::Automatic => held.then_some(1.0),
+			Trigger::Charged { time, max_multiplier } => {
+				if held {
+					self.charge = Some(f32::min(self.charge.unwrap_or(0.0) + delta, time));
+					None
+				} else {
+					self.charge.take().map(|charge| 1.0 + (max_multiplier - 1.0) * charge / time)
+				}
+			},
+		}
+	}
+
+	fn select(&mut self, index: usize) {
+		if index != self.selected {
+			self.selected = index;
+			self.charge = None;
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct WeaponLoader;
+
+impl AssetLoader for WeaponLoader {
+	fn load<'a>(
+		&'a self,
+		bytes: &'a [u8],
+		load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+		Box::pin(async move {
+			let path = load_context.path().display().to_string();
+			let weapons: WeaponDefinitions = ron::de::from_bytes(bytes)
+				.map_err(|err| anyhow::anyhow!("{}:{}", path, err))?;
+			weapons.validate()
+				.map_err(|err| anyhow::anyhow!("{}: {}", path, err))?;
+			load_context.set_default_asset(LoadedAsset::new(weapons));
+			Ok(())
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["weapons.ron"]
+	}
+}
+
+pub struct CurrentWeapons {
+	pub handle: Handle<WeaponDefinitions>,
+}
+
+pub fn load_weapons(
+	mut commands: Commands,
+	asset_server: Res<AssetServer>,
+) {
+	commands.insert_resource(CurrentWeapons {
+		handle: asset_server.load(WEAPONS_PATH),
+	});
+}
+
+//the definitions loaded right now, they're in before play starts so there's always some while playing
+#[derive(SystemParam)]
+pub struct LoadedWeapons<'w, 's> {
+	assets: Res<'w, Assets<WeaponDefinitions>>,
+	current: Res<'w, CurrentWeapons>,
+	#[system_param(ignore)]
+	marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> LoadedWeapons<'w, 's> {
+	pub fn definitions(&self) -> &WeaponDefinitions {
+		self.assets.get(&self.current.handle).unwrap()
+	}
+}
+
+//a reload can leave fewer weapons than the one that's picked, fall back to the last of them
+pub fn clamp_weapon_system(
+	mut query: Query<&mut Weapon>,
+	weapons: LoadedWeapons,
+) {
+	let count = weapons.definitions().weapons.len();
+	for mut weapon in query.iter_mut() {
+		if weapon.selected >= count {
+			weapon.select(count - 1);
+		}
+	}
+}
+
+pub fn select_weapon_system(
+	input: Res<PlayerInput>,
+	mut query: Query<&mut Weapon>,
+	weapons: LoadedWeapons,
+) {
+	if input.select.is_none() && input.cycle == 0 {
+		return;
+	}
+	let definitions = weapons.definitions();
+	let count = definitions.weapons.len();
+
+	for mut weapon in query.iter_mut() {
+		let index = match input.select {
+			//no weapon in that slot, keep the one we've got
+			Some(slot) if slot as usize >= count => continue,
+			Some(slot) => slot as usize,
+			None => (weapon.selected as i64 + input.cycle as i64).rem_euclid(count as i64) as usize,
+		};
+		weapon.select(index);
+		info!("weapon {}", definitions.weapons[index].name);
+	}
+}
+
+//goes off on whatever the shot hits first, a wall or a body
+pub fn explosion_system(
+	mut impact_events: EventReader<ShotImpact>,
+	mut hit_events: EventReader<ShotHit>,
+	shot_query: Query<(&Explosion, &Shot, &Sticky)>,
+	mut body_query: Query<(Entity, &Transform, &Sticky, &mut Physics), With<Head>>,
+	levels: Res<Assets<Level>>,
+	current_level: Res<CurrentLevel>,
+) {
+	let friendly_fire = levels.get(&current_level.handle).unwrap().friendly_fire;
+	let blasts = impact_events.iter()
+		.map(|impact| (impact.shot, impact.point))
+		.chain(hit_events.iter().map(|hit| (hit.shot, hit.point)));
+
+	let mut exploded = Vec::new();
+	for (shot, point) in blasts {
+		let (explosion, shot_info, team) = match shot_query.get(shot) {
+			Ok(shot) => shot,
+			Err(_) => continue,
+		};
+		if exploded.contains(&shot) {
+			continue;
+		}
+		exploded.push(shot);
+
+		for (body, trans, body_team, mut physics) in body_query.iter_mut() {
+			//the level's rules for who a shot can hurt go for the blast too
+			if !friendly_fire.allows(shot_info.owner, *team, body, *body_team) {
+				continue;
+			}
+			//push from the middle of the body rather than the head
+			let offset = trans.translation + Vec3::new(0.0, -3.5, 0.0) - point;
+			let distance = offset.length();
+			if distance >= explosion.radius {
+				continue;
+			}
+			let direction = if distance > f32::EPSILON { offset / distance } else { Vec3::Y };
+			physics.velocity += direction * explosion.knockback * (1.0 - distance / explosion.radius);
+		}
+	}
+}
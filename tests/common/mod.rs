@@ -0,0 +1,35 @@
+//each test file only uses some of these
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use bevy_game_jam::state::AppState;
+
+//assets load on other threads, so give them real time rather than a number of updates
+const LOAD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
+
+pub fn current_state(app: &App) -> AppState {
+	*app.world.get_resource::<State<AppState>>().unwrap().current()
+}
+
+//updates until the game gets to a state, false if it never does
+pub fn run_until(app: &mut App, state: AppState, max_updates: usize) -> bool {
+	for _ in 0..max_updates {
+		app.update();
+		if current_state(app) == state {
+			return true;
+		}
+	}
+	false
+}
+
+//updates until everything's loaded and the level is up
+pub fn start_playing(app: &mut App) {
+	let started = std::time::Instant::now();
+	while current_state(app) != AppState::Playing {
+		assert!(started.elapsed() < LOAD_TIMEOUT, "assets never finished loading");
+		app.update();
+		std::thread::sleep(std::time::Duration::from_millis(1));
+	}
+	//the level gets spawned on the way into playing, let the commands land
+	app.update();
+}
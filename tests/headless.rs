@@ -0,0 +1,188 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy::app::Events;
+use bevy::input::ElementState;
+use bevy::input::mouse::{MouseButtonInput, MouseMotion};
+use bevy::math::const_vec2;
+use bevy_game_jam::{headless_app, EnemyHead, PlayerHead};
+use bevy_game_jam::map::LevelSelect;
+use bevy_game_jam::state::{AppState, LOSE_Y};
+use bevy_game_jam::tick::TickRate;
+use common::{current_state, run_until, start_playing};
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+//one wide floor and a few enemies on it
+const DUEL_LEVEL: &str = "levels/duel.level.ron";
+//mouse movement is turned into angles as if the window were this big when there isn't one
+const WINDOW: Vec2 = const_vec2!([1280.0, 720.0]);
+//the player's shots
+const SHOT_SPEED: f32 = 20.0;
+const SHOT_GRAVITY: f32 = 6.0;
+//how far to the side of the middle of a body a shot can land and still be worth taking, bodies are 0.8 across
+const AIM_SLACK: f32 = 0.3;
+//walks up to anyone further than this, shots only just reach 67 and strafing drifts back out of range
+const CLOSE_ENOUGH: f32 = 40.0;
+//share of each update's movement that goes into an enemy's speed
+const VELOCITY_SMOOTHING: f32 = 0.1;
+//three minutes of play
+const MAX_UPDATES: usize = 10800;
+//how long to strafe one way before turning back, enemy shots land where the player was a second ago
+const STRAFE_UPDATES: usize = 90;
+
+fn player_height(app: &mut App) -> f32 {
+	let mut query = app.world.query_filtered::<&Transform, PlayerHead>();
+	query.iter(&app.world).next().unwrap().translation.y
+}
+
+//the yaw and pitch that land a shot from the head on the middle of a body, none when it's out of reach
+fn aim_at(head: Vec3, target: Vec3) -> Option<Vec2> {
+	let offset = target + Vec3::new(0.0, -3.5, 0.0) - head;
+	let across = Vec2::new(offset.x, offset.z).length();
+	let speed = SHOT_SPEED * SHOT_SPEED;
+	let reach = speed * speed - SHOT_GRAVITY * (SHOT_GRAVITY * across * across + 2.0 * offset.y * speed);
+	if reach < 0.0 {
+		return None;
+	}
+	//the flatter of the two arcs
+	let pitch = ((speed - reach.sqrt()) / (SHOT_GRAVITY * across)).atan();
+	Some(Vec2::new(offset.x.atan2(offset.z), pitch))
+}
+
+//the same for a body on the move, aimed where it'll be by the time the shot gets there
+fn lead(head: Vec3, target: Vec3, velocity: Vec3) -> Option<Vec2> {
+	let mut ahead = target;
+	for _ in 0..4 {
+		let aim = aim_at(head, ahead)?;
+		let across = Vec2::new(ahead.x - head.x, ahead.z - head.z).length();
+		let time = across / (SHOT_SPEED * aim.y.cos());
+		ahead = target + Vec3::new(velocity.x, 0.0, velocity.z) * time;
+	}
+	aim_at(head, ahead)
+}
+
+//how lining up on the nearest enemy is going
+#[derive(PartialEq)]
+enum Aim {
+	TooFar,
+	Turning,
+	LinedUp,
+}
+
+//where each enemy was last update and how fast it's been going lately
+type Tracked = HashMap<Entity, (Vec3, Vec3)>;
+
+//moves the mouse towards the nearest enemy like a player would,
+//speeds are watched rather than read off physics as friction takes some off what it holds,
+//and smoothed as they can change their minds every tick
+fn aim_at_nearest_enemy(app: &mut App, tracked: &mut Tracked) -> Aim {
+	let (head, aim) = {
+		let mut query = app.world.query_filtered::<(&Transform, &Children), PlayerHead>();
+		let (trans, children) = query.iter(&app.world).next().unwrap();
+		let camera = children.iter()
+			.find_map(|child| app.world.get::<Camera>(*child).and(app.world.get::<Transform>(*child)))
+			.unwrap();
+		(trans.translation, trans.rotation * camera.rotation * -Vec3::Z)
+	};
+	let hz = app.world.get_resource::<TickRate>().unwrap().hz as f32;
+	let mut query = app.world.query_filtered::<(Entity, &Transform), EnemyHead>();
+	let enemies: Vec<_> = query.iter(&app.world)
+		.map(|(entity, trans)| {
+			let (last, velocity) = tracked.get(&entity).copied().unwrap_or((trans.translation, Vec3::ZERO));
+			let velocity = velocity.lerp((trans.translation - last) * hz, VELOCITY_SMOOTHING);
+			tracked.insert(entity, (trans.translation, velocity));
+			(trans.translation, velocity)
+		})
+		.collect();
+	let nearest = enemies.into_iter()
+		.min_by(|(a, _), (b, _)| a.distance(head).total_cmp(&b.distance(head)));
+	let (target, velocity) = match nearest {
+		Some(enemy) => enemy,
+		None => return Aim::Turning,
+	};
+	//out of reach, face it level to walk over
+	let wanted = lead(head, target, velocity)
+		.unwrap_or_else(|| Vec2::new((target.x - head.x).atan2(target.z - head.z), 0.0));
+
+	//turning round then up, the same way the camera measures them
+	let current = Vec2::new(aim.x.atan2(aim.z), aim.y.asin());
+	let turn = Vec2::new((wanted.x - current.x + PI).rem_euclid(TAU) - PI, wanted.y - current.y);
+	//moving the mouse right turns to the right, which is the other way round to the angle
+	app.world.get_resource_mut::<Events<MouseMotion>>().unwrap().send(MouseMotion {
+		delta: Vec2::new(-turn.x / TAU * WINDOW.x, -turn.y / PI * WINDOW.y),
+	});
+	if target.distance(head) > CLOSE_ENOUGH {
+		Aim::TooFar
+	} else if turn.length() * target.distance(head) < AIM_SLACK {
+		Aim::LinedUp
+	} else {
+		Aim::Turning
+	}
+}
+
+//clicks go through the events, the input resource clears what was just pressed each update
+fn click(app: &mut App, state: ElementState) {
+	app.world.get_resource_mut::<Events<MouseButtonInput>>().unwrap().send(MouseButtonInput {
+		button: MouseButton::Left,
+		state,
+	});
+}
+
+#[test]
+fn falling_off_the_map_loses() {
+	let mut app = headless_app();
+	start_playing(&mut app);
+
+	//the arena has no walls, running forward goes straight off the edge
+	app.world.get_resource_mut::<Input<KeyCode>>().unwrap().press(KeyCode::W);
+	assert!(run_until(&mut app, AppState::GameOver, 2000));
+	assert!(player_height(&mut app) < LOSE_Y);
+}
+
+#[test]
+fn clearing_the_last_wave_wins() {
+	let mut app = headless_app();
+	app.insert_resource(LevelSelect {
+		path: DUEL_LEVEL.to_string(),
+	});
+	start_playing(&mut app);
+
+	//aims at whoever's closest and fires whenever it's lined up, letting go the update after,
+	//walking over to anyone far off and strafing the whole time
+	let mut pressed = false;
+	let mut tracked = Tracked::new();
+	for update in 0..MAX_UPDATES {
+		let aim = aim_at_nearest_enemy(&mut app, &mut tracked);
+		let (strafe, other) = match (update / STRAFE_UPDATES) % 2 {
+			0 => (KeyCode::A, KeyCode::D),
+			_ => (KeyCode::D, KeyCode::A),
+		};
+		let mut keys = app.world.get_resource_mut::<Input<KeyCode>>().unwrap();
+		keys.release(other);
+		keys.press(strafe);
+		if aim == Aim::TooFar {
+			keys.press(KeyCode::W);
+		} else {
+			keys.release(KeyCode::W);
+		}
+
+		if pressed {
+			click(&mut app, ElementState::Released);
+			pressed = false;
+		} else if aim == Aim::LinedUp {
+			click(&mut app, ElementState::Pressed);
+			pressed = true;
+		}
+
+		app.update();
+		match current_state(&app) {
+			AppState::Playing => {},
+			state => {
+				assert_eq!(state, AppState::Win);
+				return;
+			},
+		}
+	}
+	panic!("the last wave was never cleared");
+}
@@ -0,0 +1,19 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_game_jam::headless_app;
+use bevy_game_jam::map::{Collision, LevelSelect};
+
+#[test]
+fn gltf_level_can_be_picked() {
+	let mut app = headless_app();
+	app.insert_resource(LevelSelect {
+		path: "levels/walls.level.ron".to_string(),
+	});
+	common::start_playing(&mut app);
+
+	let mut query = app.world.query_filtered::<&Transform, With<Collision>>();
+	let walls: Vec<&Transform> = query.iter(&app.world).collect();
+	assert_eq!(walls.len(), 1);
+	assert!(walls[0].scale.abs_diff_eq(Vec3::new(11.041_24, 0.5, 11.041_24), 1e-4));
+}
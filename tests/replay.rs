@@ -0,0 +1,131 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy::app::Events;
+use bevy::input::mouse::MouseMotion;
+use bevy_game_jam::{HeadlessPlugins, SimulationPlugins, PlayerHead};
+use bevy_game_jam::map::{LevelSelect, DEFAULT_LEVEL_PATH};
+use bevy_game_jam::replay::{Replay, ReplayRecorder, ReplayStatus, CHECKSUM_INTERVAL};
+use bevy_game_jam::rng::GameRng;
+use bevy_game_jam::tick::{Interpolated, TickCount, TickRate};
+use common::start_playing;
+use std::path::{Path, PathBuf};
+
+const SEED: u64 = 7;
+const TICK_RATE: f64 = 60.0;
+const RECORDED_TICKS: usize = 300;
+
+fn replay_path(name: &str) -> PathBuf {
+	std::env::temp_dir().join(format!("bevy_game_jam_{}_{}.replay", name, std::process::id()))
+}
+
+fn replay_app(rng: GameRng) -> App {
+	let mut app = App::new();
+	app.insert_resource(TickRate {
+			hz: TICK_RATE,
+			lockstep: true,
+		})
+		.insert_resource(rng);
+	app
+}
+
+fn tick_count(app: &App) -> u64 {
+	app.world.get_resource::<TickCount>().unwrap().count
+}
+
+//runs forward then sideways then jumps, turning the whole time
+fn record(path: &Path) {
+	let recorder = ReplayRecorder::create(path.to_str().unwrap(), SEED, TICK_RATE, DEFAULT_LEVEL_PATH).unwrap();
+	let mut app = replay_app(GameRng::new(SEED));
+	app.insert_resource(recorder)
+		.add_plugins(HeadlessPlugins)
+		.add_plugins(SimulationPlugins);
+	start_playing(&mut app);
+
+	for update in 0..RECORDED_TICKS {
+		let mut keys = app.world.get_resource_mut::<Input<KeyCode>>().unwrap();
+		//short enough that it never gets near an edge
+		match update {
+			10 => keys.press(KeyCode::W),
+			40 => {
+				keys.release(KeyCode::W);
+				keys.press(KeyCode::D);
+			},
+			70 => {
+				keys.release(KeyCode::D);
+				keys.press(KeyCode::Space);
+			},
+			_ => {},
+		}
+		app.world.get_resource_mut::<Events<MouseMotion>>().unwrap().send(MouseMotion {
+			delta: Vec2::new(3.0, 0.5),
+		});
+		app.update();
+	}
+}
+
+fn play(path: &Path) -> App {
+	let replay = Replay::load(path.to_str().unwrap()).unwrap();
+	assert_eq!(replay.seed, SEED);
+	assert_eq!(replay.level, DEFAULT_LEVEL_PATH);
+	let mut app = replay_app(GameRng::new(replay.seed));
+	app.insert_resource(LevelSelect {
+			path: replay.level.clone(),
+		})
+		.insert_resource(replay)
+		.add_plugins(HeadlessPlugins)
+		.add_plugins(SimulationPlugins);
+	start_playing(&mut app);
+	app
+}
+
+fn status(app: &App) -> &ReplayStatus {
+	app.world.get_resource::<ReplayStatus>().unwrap()
+}
+
+fn play_out(app: &mut App) {
+	for _ in 0..RECORDED_TICKS * 2 {
+		if status(app).finished {
+			return;
+		}
+		app.update();
+	}
+	panic!("the replay never finished");
+}
+
+#[test]
+fn replay_matches_recording() {
+	let path = replay_path("roundtrip");
+	record(&path);
+	let mut app = play(&path);
+	std::fs::remove_file(&path).unwrap();
+
+	play_out(&mut app);
+	assert_eq!(status(&app).diverged_at, None);
+	assert!(status(&app).checksums_matched >= RECORDED_TICKS / CHECKSUM_INTERVAL as usize - 1);
+}
+
+#[test]
+fn replay_reports_first_divergent_tick() {
+	let path = replay_path("divergence");
+	record(&path);
+	let mut app = play(&path);
+	std::fs::remove_file(&path).unwrap();
+
+	for _ in 0..RECORDED_TICKS / 2 {
+		app.update();
+	}
+	assert_eq!(status(&app).diverged_at, None);
+
+	//the tick puts the player back to where the simulation left it, so move that instead
+	let mut query = app.world.query_filtered::<(Entity, &Transform), PlayerHead>();
+	let (player, trans) = query.iter(&app.world).next().unwrap();
+	let moved = trans.translation + Vec3::new(1.0, 0.0, 0.0);
+	app.world.entity_mut(player).insert(Interpolated::new(moved));
+	//the next tick is the first to see the change, the first checksum from then on should catch it
+	let next_tick = tick_count(&app) + 1;
+	let expected = next_tick.div_ceil(CHECKSUM_INTERVAL) * CHECKSUM_INTERVAL;
+
+	play_out(&mut app);
+	assert_eq!(status(&app).diverged_at, Some(expected));
+}